@@ -0,0 +1,3 @@
+ALTER TABLE comments
+    DROP COLUMN attachments,
+    DROP COLUMN parent_id;
\ No newline at end of file
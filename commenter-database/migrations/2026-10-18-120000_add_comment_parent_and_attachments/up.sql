@@ -0,0 +1,3 @@
+ALTER TABLE comments
+    ADD COLUMN parent_id character(36),
+    ADD COLUMN attachments text[] NOT NULL DEFAULT '{}';
\ No newline at end of file
@@ -9,5 +9,8 @@ diesel::table! {
         #[max_length = 1024]
         text -> Varchar,
         state -> Int4,
+        #[max_length = 36]
+        parent_id -> Nullable<Bpchar>,
+        attachments -> Array<Text>,
     }
 }
@@ -0,0 +1,98 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+// edge is a binary, modules the registry depends on are compiled into the benchmark itself
+#[allow(dead_code, unused_imports)]
+#[path = "../src/registry.rs"]
+mod registry;
+#[allow(dead_code, unused_imports)]
+#[path = "../src/subscription.rs"]
+mod subscription;
+mod stomp {
+    pub use commenter_stomp::stomp::AckMode;
+}
+
+use registry::Registry;
+use stomp::AckMode;
+
+const DESTINATIONS: usize = 64;
+const SUBSCRIBERS: usize = 10_000;
+const THREADS: usize = 8;
+const OPERATIONS: usize = 1_000;
+
+fn destination(index: usize) -> String {
+    format!("article-{}", index % DESTINATIONS)
+}
+
+/// Registry with `SUBSCRIBERS` users spread over `DESTINATIONS` destinations.
+fn registry(shards: usize) -> Registry {
+    let registry = Registry::new(shards);
+
+    for user_id in 0..SUBSCRIBERS {
+        registry
+            .subscribe(user_id, &destination(user_id), "sub-0", AckMode::Auto)
+            .unwrap();
+    }
+
+    registry
+}
+
+/// Half of the threads broadcast to destinations, the other half keep subscribing, unsubscribing
+/// and disconnecting users that are not subscribed anywhere else.
+fn concurrent_load(registry: &Registry, iterations: u64) -> Duration {
+    let start = Instant::now();
+
+    for _ in 0..iterations {
+        thread::scope(|scope| {
+            for thread in 0..THREADS {
+                scope.spawn(move || {
+                    let user_id = SUBSCRIBERS + thread;
+
+                    for operation in 0..OPERATIONS {
+                        let destination = destination(operation + thread);
+
+                        if thread % 2 == 0 {
+                            black_box(registry.with_group(&destination, |group| group.len()));
+                        } else {
+                            let subscription = format!("sub-{operation}");
+                            registry
+                                .subscribe(user_id, &destination, &subscription, AckMode::Auto)
+                                .unwrap();
+                            registry.unsubscribe(user_id, &subscription).unwrap();
+                        }
+                    }
+
+                    registry.remove_user(user_id);
+                });
+            }
+        });
+    }
+
+    start.elapsed()
+}
+
+fn bench_registry_concurrent_load(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Registry under concurrent load");
+
+    // single shard behaves like one lock guarding all destinations
+    for shards in [1, 32] {
+        let registry = registry(shards);
+
+        group.bench_with_input(
+            BenchmarkId::new("shards", shards),
+            &registry,
+            |bencher, registry| {
+                bencher.iter_custom(|iterations| concurrent_load(registry, iterations))
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_registry_concurrent_load);
+criterion_main!(benches);
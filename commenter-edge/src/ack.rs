@@ -0,0 +1,228 @@
+use std::collections::{HashMap, VecDeque};
+
+use anyhow::{bail, Result};
+use tokio::time::{Duration, Instant};
+
+use crate::stomp::{AckMode, Delivery};
+
+/// MESSAGE frame sent to a subscription that requires acknowledgement.
+#[derive(Clone, Debug)]
+pub struct Unacknowledged {
+    /// Value of frame's `ack` header, client refers to the message by it.
+    pub ack: String,
+    pub subscription: String,
+    pub frame: Delivery,
+    pub sent_at: Instant,
+    /// Number of times the frame was sent to the client so far.
+    pub deliveries: u32,
+}
+
+/// Messages sent over a single connection that were neither acknowledged nor rejected yet,
+/// kept per subscription in the order they were sent.
+#[derive(Default, Debug)]
+pub struct PendingAcks {
+    subscriptions: HashMap<String, PendingSubscription>,
+}
+
+#[derive(Debug)]
+struct PendingSubscription {
+    mode: AckMode,
+    messages: VecDeque<Unacknowledged>,
+}
+
+impl PendingAcks {
+    /// Starts tracking message sent to `mode` subscription, messages of auto subscriptions are not tracked.
+    pub fn sent(&mut self, mode: AckMode, message: Unacknowledged) {
+        if mode == AckMode::Auto {
+            return;
+        }
+
+        self.subscriptions
+            .entry(message.subscription.clone())
+            .or_insert_with(|| PendingSubscription {
+                mode,
+                messages: VecDeque::new(),
+            })
+            .messages
+            .push_back(message);
+    }
+
+    /// Tracks message sent again at `now`, unless its subscription was cancelled in the meantime.
+    pub fn resent(&mut self, mut message: Unacknowledged, now: Instant) {
+        if let Some(subscription) = self.subscriptions.get_mut(&message.subscription) {
+            message.sent_at = now;
+            message.deliveries += 1;
+            subscription.messages.push_back(message);
+        }
+    }
+
+    /// Confirms message `ack`. In client mode all messages sent to the same subscription
+    /// before it are confirmed as well.
+    pub fn ack(&mut self, ack: &str) -> Result<()> {
+        self.take(ack).map(|_| ())
+    }
+
+    /// Rejects message `ack` (in client mode together with messages sent before it),
+    /// rejected messages are handed back so they can be redelivered.
+    pub fn nack(&mut self, ack: &str) -> Result<Vec<Unacknowledged>> {
+        self.take(ack)
+    }
+
+    /// Takes out messages that were not acknowledged within `timeout` since they were sent.
+    pub fn expired(&mut self, now: Instant, timeout: Duration) -> Vec<Unacknowledged> {
+        let mut expired = Vec::new();
+
+        for subscription in self.subscriptions.values_mut() {
+            // messages are kept in the order they were sent, so the oldest ones are at the front
+            while subscription
+                .messages
+                .front()
+                .is_some_and(|message| now.duration_since(message.sent_at) >= timeout)
+            {
+                expired.extend(subscription.messages.pop_front());
+            }
+        }
+
+        expired
+    }
+
+    /// Stops tracking messages of a cancelled subscription.
+    pub fn unsubscribe(&mut self, subscription: &str) {
+        self.subscriptions.remove(subscription);
+    }
+
+    fn take(&mut self, ack: &str) -> Result<Vec<Unacknowledged>> {
+        for subscription in self.subscriptions.values_mut() {
+            let Some(position) = subscription
+                .messages
+                .iter()
+                .position(|message| message.ack == ack)
+            else {
+                continue;
+            };
+
+            return Ok(match subscription.mode {
+                AckMode::ClientIndividual => {
+                    subscription.messages.remove(position).into_iter().collect()
+                }
+                _ => subscription.messages.drain(..=position).collect(),
+            });
+        }
+
+        bail!("Message {ack} is not waiting for acknowledgement")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stomp::{BroadcastFrame, StompFrame};
+    use spectral::prelude::*;
+
+    #[test]
+    fn pending_acks_should_not_track_auto_subscriptions() {
+        let mut pending = PendingAcks::default();
+
+        pending.sent(AckMode::Auto, message("ack-1", "sub-1"));
+
+        assert_that(&pending.ack("ack-1")).is_err();
+    }
+
+    #[test]
+    fn pending_acks_should_confirm_preceding_messages_in_client_mode() {
+        let mut pending = PendingAcks::default();
+        pending.sent(AckMode::Client, message("ack-1", "sub-1"));
+        pending.sent(AckMode::Client, message("ack-2", "sub-1"));
+        pending.sent(AckMode::Client, message("ack-3", "sub-1"));
+
+        assert_that(&pending.ack("ack-2")).is_ok();
+
+        assert_that(&pending.ack("ack-1")).is_err();
+        assert_that(&pending.ack("ack-3")).is_ok();
+    }
+
+    #[test]
+    fn pending_acks_should_confirm_single_message_in_client_individual_mode() {
+        let mut pending = PendingAcks::default();
+        pending.sent(AckMode::ClientIndividual, message("ack-1", "sub-1"));
+        pending.sent(AckMode::ClientIndividual, message("ack-2", "sub-1"));
+
+        assert_that(&pending.ack("ack-2")).is_ok();
+
+        assert_that(&pending.ack("ack-2")).is_err();
+        assert_that(&pending.ack("ack-1")).is_ok();
+    }
+
+    #[test]
+    fn pending_acks_should_hand_back_rejected_messages() {
+        let mut pending = PendingAcks::default();
+        pending.sent(AckMode::Client, message("ack-1", "sub-1"));
+        pending.sent(AckMode::Client, message("ack-2", "sub-1"));
+        pending.sent(AckMode::ClientIndividual, message("ack-3", "sub-2"));
+
+        let rejected = pending.nack("ack-2").unwrap();
+
+        assert_that(&acks(&rejected)).is_equal_to(vec!["ack-1", "ack-2"]);
+        assert_that(&pending.ack("ack-3")).is_ok();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn pending_acks_should_take_out_expired_messages() {
+        let timeout = Duration::from_secs(30);
+        let mut pending = PendingAcks::default();
+        pending.sent(AckMode::Client, message("ack-1", "sub-1"));
+
+        tokio::time::advance(Duration::from_secs(20)).await;
+        pending.sent(AckMode::Client, message("ack-2", "sub-1"));
+        tokio::time::advance(Duration::from_secs(10)).await;
+
+        let expired = pending.expired(Instant::now(), timeout);
+
+        assert_that(&acks(&expired)).is_equal_to(vec!["ack-1"]);
+        assert_that(&pending.ack("ack-2")).is_ok();
+    }
+
+    #[test]
+    fn pending_acks_should_track_resent_message_again() {
+        let mut pending = PendingAcks::default();
+        pending.sent(AckMode::Client, message("ack-1", "sub-1"));
+
+        let rejected = pending.nack("ack-1").unwrap().remove(0);
+        pending.resent(rejected, Instant::now());
+
+        let rejected = pending.nack("ack-1").unwrap();
+        assert_that(&rejected[0].deliveries).is_equal_to(2);
+    }
+
+    #[test]
+    fn pending_acks_should_forget_cancelled_subscription() {
+        let mut pending = PendingAcks::default();
+        pending.sent(AckMode::Client, message("ack-1", "sub-1"));
+
+        pending.unsubscribe("sub-1");
+
+        assert_that(&pending.ack("ack-1")).is_err();
+    }
+
+    fn message(ack: &str, subscription: &str) -> Unacknowledged {
+        Unacknowledged {
+            ack: ack.to_owned(),
+            subscription: subscription.to_owned(),
+            frame: Delivery {
+                frame: BroadcastFrame::new(&StompFrame::broadcast_message("topic-1", ack, "text")),
+                comment_id: "comment-1".into(),
+                subscription: subscription.to_owned(),
+                ack: Some(ack.to_owned()),
+            },
+            sent_at: Instant::now(),
+            deliveries: 1,
+        }
+    }
+
+    fn acks(messages: &[Unacknowledged]) -> Vec<&str> {
+        messages
+            .iter()
+            .map(|message| message.ack.as_str())
+            .collect()
+    }
+}
@@ -1,15 +1,29 @@
 include!(concat!(env!("OUT_DIR"), "/comments.rs"));
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use uuid::Uuid;
 
+/// Length of `text` column of comments table, `varchar(1024)` counts characters rather than bytes.
+const MAX_TEXT_LENGTH: usize = 1024;
+
 impl Comment {
+    /// Rejects text that would not fit into the database, so it fails before reaching Kafka.
+    pub fn check_text(text: &str) -> Result<()> {
+        if text.chars().count() > MAX_TEXT_LENGTH {
+            bail!("Comment text exceeds maximum length of {MAX_TEXT_LENGTH} characters");
+        }
+
+        Ok(())
+    }
+
     pub fn new_create(destination: String, text: String) -> Comment {
         Comment {
             id: Uuid::new_v4().to_string(),
             group_id: destination,
-            text: text,
+            text,
             state: CommentState::Created.into(),
+            parent_id: None,
+            attachments: Vec::new(),
         }
     }
 
@@ -19,8 +33,10 @@ impl Comment {
         Ok(Comment {
             id,
             group_id: stored_comment.group_id,
-            text: text,
+            text,
             state: CommentState::Updated.into(),
+            parent_id: stored_comment.parent_id,
+            attachments: stored_comment.attachments,
         })
     }
 
@@ -29,9 +45,8 @@ impl Comment {
 
         Ok(Comment {
             id,
-            group_id: stored_comment.group_id,
-            text: stored_comment.text,
             state: CommentState::Deleted.into(),
+            ..stored_comment
         })
     }
 
@@ -44,3 +59,15 @@ impl Comment {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spectral::prelude::*;
+
+    #[test]
+    fn check_text_should_count_characters_rather_than_bytes() {
+        assert_that(&Comment::check_text(&"ž".repeat(MAX_TEXT_LENGTH))).is_ok();
+        assert_that(&Comment::check_text(&"x".repeat(MAX_TEXT_LENGTH + 1))).is_err();
+    }
+}
@@ -0,0 +1,98 @@
+use commenter_stomp::stomp::{HeartBeat, ParserLimits};
+use std::{env, time::Duration};
+
+use crate::{outbound::OverflowPolicy, transaction::TransactionLimits};
+
+pub use commenter_recovery::RecoveryConfig;
+
+const DEFAULT_HEART_BEAT_MS: u32 = 10000;
+const DEFAULT_ACK_TIMEOUT_MS: u32 = 30000;
+const DEFAULT_MAX_DELIVERIES: u32 = 3;
+const DEFAULT_MAX_FRAME_BYTES: u32 = 8192;
+const DEFAULT_MAX_HEADERS: u32 = 32;
+const DEFAULT_MAX_HEADER_LINE_BYTES: u32 = 1024;
+// comment text is stored in varchar(1024) column, 1024 characters take at most 4096 bytes in UTF-8
+const DEFAULT_MAX_BODY_BYTES: u32 = 4096;
+const DEFAULT_MAX_OPEN_TRANSACTIONS: u32 = 8;
+const DEFAULT_MAX_TRANSACTION_FRAMES: u32 = 64;
+const DEFAULT_OUTBOUND_QUEUE_CAPACITY: u32 = 256;
+const DEFAULT_OVERFLOW_POLICY: OverflowPolicy = OverflowPolicy::Disconnect;
+
+/// Per connection settings, shared by all websocket connections handled by the edge.
+#[derive(Clone, Copy, Debug)]
+pub struct ConnectionConfig {
+    /// Heart-beat announced in CONNECTED frame, final intervals are negotiated with each client.
+    pub heart_beat: HeartBeat,
+    /// Limits of frames received from clients, connection is closed with ERROR when exceeded.
+    pub limits: ParserLimits,
+    pub transaction_limits: TransactionLimits,
+    /// Number of frames waiting to be sent to a client, before `overflow_policy` applies.
+    pub outbound_capacity: usize,
+    pub overflow_policy: OverflowPolicy,
+}
+
+impl ConnectionConfig {
+    pub fn from_env() -> ConnectionConfig {
+        ConnectionConfig {
+            heart_beat: HeartBeat {
+                outgoing: env_or_default("HEART_BEAT_OUTGOING_MS", DEFAULT_HEART_BEAT_MS),
+                incoming: env_or_default("HEART_BEAT_INCOMING_MS", DEFAULT_HEART_BEAT_MS),
+            },
+            limits: ParserLimits {
+                max_frame_length: env_or_default("MAX_FRAME_BYTES", DEFAULT_MAX_FRAME_BYTES)
+                    as usize,
+                max_headers: env_or_default("MAX_HEADERS", DEFAULT_MAX_HEADERS) as usize,
+                max_header_line_length: env_or_default(
+                    "MAX_HEADER_LINE_BYTES",
+                    DEFAULT_MAX_HEADER_LINE_BYTES,
+                ) as usize,
+                max_body_length: env_or_default("MAX_BODY_BYTES", DEFAULT_MAX_BODY_BYTES) as usize,
+            },
+            transaction_limits: TransactionLimits {
+                max_open: env_or_default("MAX_OPEN_TRANSACTIONS", DEFAULT_MAX_OPEN_TRANSACTIONS)
+                    as usize,
+                max_frames: env_or_default("MAX_TRANSACTION_FRAMES", DEFAULT_MAX_TRANSACTION_FRAMES)
+                    as usize,
+            },
+            outbound_capacity: env_or_default(
+                "OUTBOUND_QUEUE_CAPACITY",
+                DEFAULT_OUTBOUND_QUEUE_CAPACITY,
+            ) as usize,
+            overflow_policy: match env::var("OUTBOUND_OVERFLOW_POLICY") {
+                Ok(value) => value
+                    .parse()
+                    .unwrap_or_else(|err| panic!("OUTBOUND_OVERFLOW_POLICY is invalid: {err}")),
+                Err(_) => DEFAULT_OVERFLOW_POLICY,
+            },
+        }
+    }
+}
+
+/// Settings of subscriptions in client and client-individual ack modes.
+#[derive(Clone, Copy, Debug)]
+pub struct AckConfig {
+    /// Message that was neither acknowledged nor rejected within this time is redelivered.
+    pub timeout: Duration,
+    /// Number of times a message is sent before the connection is closed with ERROR.
+    pub max_deliveries: u32,
+}
+
+impl AckConfig {
+    pub fn from_env() -> AckConfig {
+        AckConfig {
+            timeout: Duration::from_millis(
+                env_or_default("ACK_TIMEOUT_MS", DEFAULT_ACK_TIMEOUT_MS).into(),
+            ),
+            max_deliveries: env_or_default("ACK_MAX_DELIVERIES", DEFAULT_MAX_DELIVERIES),
+        }
+    }
+}
+
+fn env_or_default(key: &str, default: u32) -> u32 {
+    match env::var(key) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|_| panic!("{key} should be valid u32")),
+        Err(_) => default,
+    }
+}
@@ -0,0 +1,108 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, RwLock},
+};
+
+use crate::{ack::PendingAcks, outbound::OutboundSender};
+
+/// State of a single client connection that comments are delivered to.
+#[derive(Debug)]
+pub struct Connection {
+    pub sender: OutboundSender,
+    /// Messages waiting for ACK or NACK, locked only for subscriptions that require them.
+    pub acks: Mutex<PendingAcks>,
+}
+
+type Shard = RwLock<HashMap<usize, Arc<Connection>>>;
+
+/// Connections of users, spread over shards by user id so that adding and removing users
+/// does not hold up delivery to others. Connections are handed out as `Arc`, so no shard is
+/// locked while frames are being sent.
+///
+/// Locks are never held across `.await`, a shard is never locked while `acks` of a connection are.
+#[derive(Debug)]
+pub struct Connections {
+    shards: Box<[Shard]>,
+}
+
+impl Connections {
+    pub fn new(shards: usize) -> Connections {
+        assert!(shards > 0, "Connections need at least one shard");
+
+        Connections {
+            shards: (0..shards).map(|_| RwLock::default()).collect(),
+        }
+    }
+
+    pub fn insert(&self, user_id: usize, sender: OutboundSender) {
+        let connection = Arc::new(Connection {
+            sender,
+            acks: Mutex::default(),
+        });
+
+        self.shard(user_id)
+            .write()
+            .unwrap()
+            .insert(user_id, connection);
+    }
+
+    pub fn remove(&self, user_id: usize) -> Option<Arc<Connection>> {
+        self.shard(user_id).write().unwrap().remove(&user_id)
+    }
+
+    pub fn get(&self, user_id: usize) -> Option<Arc<Connection>> {
+        self.shard(user_id).read().unwrap().get(&user_id).cloned()
+    }
+
+    pub fn contains(&self, user_id: usize) -> bool {
+        self.shard(user_id).read().unwrap().contains_key(&user_id)
+    }
+
+    /// Connections at the time of the call, shards are locked one at a time.
+    pub fn all(&self) -> Vec<Arc<Connection>> {
+        self.shards
+            .iter()
+            .flat_map(|shard| shard.read().unwrap().values().cloned().collect::<Vec<_>>())
+            .collect()
+    }
+
+    fn shard(&self, user_id: usize) -> &Shard {
+        &self.shards[user_id % self.shards.len()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::outbound::{self, OverflowPolicy};
+    use spectral::prelude::*;
+
+    #[test]
+    fn connections_should_be_spread_over_shards() {
+        let connections = Connections::new(4);
+
+        for user_id in 1..=6 {
+            connections.insert(user_id, sender());
+        }
+
+        assert_that(&connections.all()).has_length(6);
+        assert_that(&connections.contains(5)).is_true();
+        assert_that(&connections.get(7)).is_none();
+    }
+
+    #[test]
+    fn removed_connection_should_outlive_its_shard_entry() {
+        let connections = Connections::new(4);
+        connections.insert(1, sender());
+        let connection = connections.get(1).unwrap();
+
+        assert_that(&connections.remove(1)).is_some();
+
+        assert_that(&connections.contains(1)).is_false();
+        assert_that(&connection.sender.dropped()).is_equal_to(0);
+    }
+
+    fn sender() -> OutboundSender {
+        outbound::channel(1, OverflowPolicy::DropOldest).0
+    }
+}
@@ -1,7 +1,7 @@
 use anyhow::{bail, Result};
 use prost::Message;
 use std::{
-    collections::{HashMap, HashSet},
+    collections::HashMap,
     sync::{
         atomic::{AtomicUsize, Ordering},
         Arc,
@@ -11,156 +11,549 @@ use std::{
 
 use rdkafka::{
     consumer::{Consumer, StreamConsumer},
-    producer::{FutureProducer, FutureRecord},
+    error::KafkaResult,
+    message::OwnedHeaders,
+    producer::{FutureProducer, FutureRecord, Producer},
     ClientConfig,
 };
-use tokio::sync::{
-    mpsc::{self, UnboundedSender},
-    RwLock,
+use uuid::Uuid;
+
+use commenter_recovery::Backoff;
+
+use futures_util::future;
+use tokio::{
+    sync::Mutex,
+    task,
+    time::{self, Instant},
 };
 
 use crate::{
+    ack::{PendingAcks, Unacknowledged},
     comments::Comment,
-    stomp::{SendClientFrame, StompClientFrame, StompFrame},
+    config::{AckConfig, RecoveryConfig},
+    connection::{Connection, Connections},
+    outbound::OutboundSender,
+    payload::CommentPayload,
+    registry::Registry,
+    stomp::{
+        self, AckMode, BroadcastFrame, Delivery, SendClientFrame, SendMetadata, StompClientFrame,
+        StompFrame,
+    },
+    transaction::{TransactionLimits, Transactions},
 };
 
-type Users = Arc<RwLock<HashMap<usize, mpsc::UnboundedSender<StompFrame>>>>;
-
 static NEXT_USER_ID: AtomicUsize = AtomicUsize::new(1);
 
 pub struct ApplicationContext {
-    distribution_map: RwLock<HashMap<String, HashSet<usize>>>,
+    registry: Registry,
     producer: FutureProducer,
+    /// Publish comments of committed STOMP transactions. Each producer runs a single Kafka
+    /// transaction at a time, so commits of users sharing a producer wait for each other.
+    transactional_producers: Box<[Mutex<FutureProducer>]>,
     consumer: StreamConsumer,
-    users: Users,
+    connections: Connections,
+    ack_config: AckConfig,
+    /// Transactions started by users, keyed by user id.
+    transactions: Mutex<HashMap<usize, Transactions>>,
+    transaction_limits: TransactionLimits,
 }
 
 impl ApplicationContext {
-    const CONSUMER_GROUP_ID: &str = "commenter-edge";
+    /// Prefix of consumer group ids, each instance joins a group of its own.
+    const CONSUMER_GROUP_PREFIX: &str = "commenter-edge";
     const TOPIC: &str = "comments";
+    const REDELIVERY_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+    const TRANSACTION_TIMEOUT: Duration = Duration::from_secs(10);
+    const TRANSACTIONAL_PRODUCERS: usize = 4;
+    const REGISTRY_SHARDS: usize = 32;
+    const CONNECTION_SHARDS: usize = 32;
+
+    pub fn new(
+        kafka_brokers: &str,
+        ack_config: AckConfig,
+        transaction_limits: TransactionLimits,
+    ) -> Result<ApplicationContext> {
+        let instance_id = Uuid::new_v4();
 
-    pub fn new(kafka_brokers: &str) -> ApplicationContext {
         let producer = ClientConfig::new()
             .set("bootstrap.servers", kafka_brokers)
             .set("message.timeout.ms", "5000")
-            .create()
-            .expect("Kafka producer created");
+            .create()?;
+
+        // transactional ids have to be unique, otherwise producers would fence each other off
+        let transactional_producers = (0..ApplicationContext::TRANSACTIONAL_PRODUCERS)
+            .map(|index| {
+                let producer: FutureProducer = ClientConfig::new()
+                    .set("bootstrap.servers", kafka_brokers)
+                    .set("message.timeout.ms", "5000")
+                    .set(
+                        "transactional.id",
+                        format!("commenter-edge-{instance_id}-{index}"),
+                    )
+                    .create()?;
 
+                producer.init_transactions(ApplicationContext::TRANSACTION_TIMEOUT)?;
+                Ok(Mutex::new(producer))
+            })
+            .collect::<Result<_>>()?;
+
+        // every instance has to see all comments to deliver them to its own subscribers, so instances
+        // do not share a consumer group, and as subscribers only get comments posted after they
+        // subscribed, an instance starts at the latest offset and never commits
         let consumer = ClientConfig::new()
-            .set("group.id", ApplicationContext::CONSUMER_GROUP_ID)
+            .set(
+                "group.id",
+                format!(
+                    "{}-{instance_id}",
+                    ApplicationContext::CONSUMER_GROUP_PREFIX
+                ),
+            )
             .set("bootstrap.servers", kafka_brokers)
             .set("enable.partition.eof", "false")
             .set("session.timeout.ms", "6000")
-            .set("enable.auto.commit", "true")
-            .create()
-            .expect("Kafka consumer created");
+            .set("auto.offset.reset", "latest")
+            .set("enable.auto.commit", "false")
+            .create()?;
 
-        return ApplicationContext {
+        Ok(ApplicationContext {
             producer,
+            transactional_producers,
             consumer,
-            users: Users::default(),
-            distribution_map: RwLock::new(HashMap::new()),
-        };
+            connections: Connections::new(ApplicationContext::CONNECTION_SHARDS),
+            registry: Registry::new(ApplicationContext::REGISTRY_SHARDS),
+            ack_config,
+            transactions: Mutex::new(HashMap::new()),
+            transaction_limits,
+        })
     }
 
-    pub async fn add_user(&self, sender: UnboundedSender<StompFrame>) -> usize {
+    pub async fn add_user(&self, sender: OutboundSender) -> usize {
         let user_id = NEXT_USER_ID.fetch_add(1, Ordering::Relaxed);
-        self.users.write().await.insert(user_id, sender);
-        return user_id;
+        self.connections.insert(user_id, sender);
+        user_id
     }
 
     pub async fn remove_user(&self, user_id: usize) {
-        self.remove_user_from_distribution_map(user_id).await;
-        self.users.write().await.remove(&user_id);
+        self.registry.remove_user(user_id);
+        self.connections.remove(user_id);
+        self.transactions.lock().await.remove(&user_id);
     }
 
-    pub async fn handle_client_frame(&self, user_id: usize, frame: StompClientFrame) -> Result<()> {
+    /// Processes `frame` sent by user `user_id`. Frames that are part of a `transaction`
+    /// are only buffered until the transaction is committed.
+    pub async fn handle_client_frame(
+        &self,
+        user_id: usize,
+        frame: StompClientFrame<'_>,
+        transaction: Option<&str>,
+    ) -> Result<()> {
+        if let Some(transaction) = transaction {
+            return self
+                .transactions
+                .lock()
+                .await
+                .entry(user_id)
+                .or_insert_with(|| Transactions::new(self.transaction_limits))
+                .add(transaction, frame.into_owned());
+        }
+
         match frame {
+            StompClientFrame::CONNECT(_) | StompClientFrame::STOMP(_) => {
+                bail!("Connection was already established")
+            }
             StompClientFrame::SEND(send_frme) => self.send(send_frme).await,
-            StompClientFrame::SUBSCRIBE { destination, id } => {
-                self.subscribe(user_id, destination).await
+            StompClientFrame::SUBSCRIBE {
+                destination,
+                id,
+                ack,
+            } => {
+                self.subscribe(user_id, destination.into_owned(), id.into_owned(), ack)
+                    .await
             }
-            StompClientFrame::UNSUBSCRIBE(destination) => {
-                self.unsubscribe(user_id, destination).await
+            StompClientFrame::UNSUBSCRIBE(id) => self.unsubscribe(user_id, &id).await,
+            StompClientFrame::ACK(id) => self.ack(user_id, &id),
+            StompClientFrame::NACK(id) => self.nack(user_id, &id),
+            StompClientFrame::BEGIN(transaction) => self
+                .transactions
+                .lock()
+                .await
+                .entry(user_id)
+                .or_insert_with(|| Transactions::new(self.transaction_limits))
+                .begin(&transaction),
+            StompClientFrame::COMMIT(transaction) => {
+                let frames = self.end_transaction(user_id, &transaction).await?;
+                self.commit(user_id, frames).await
+            }
+            StompClientFrame::ABORT(transaction) => {
+                self.end_transaction(user_id, &transaction).await?;
+                Ok(())
             }
             StompClientFrame::DISCONNECT => Ok(()),
         }
     }
 
-    pub async fn listen_blocking(&self) {
-        self.consumer
-            .subscribe(&[ApplicationContext::TOPIC])
-            .expect("Subscribed to topic");
+    /// Delivers comments consumed from Kafka to subscribers. Receiving is retried with backoff,
+    /// error is returned once it failed `max_failures` times in a row. Records that can not be
+    /// decoded are skipped.
+    pub async fn listen(&self, config: RecoveryConfig) -> Result<()> {
+        self.consumer.subscribe(&[ApplicationContext::TOPIC])?;
+        let mut backoff = Backoff::new(&config);
 
         loop {
-            match self.consumer.recv().await {
-                Ok(msg) => {
-                    if let Some(payload) = rdkafka::Message::payload(&msg) {
-                        match Comment::decode(payload) {
-                            Ok(comment) => {
-                                let distibution_group_read_lock =
-                                    self.distribution_map.read().await;
-
-                                if let Some(distribution_group) =
-                                    distibution_group_read_lock.get(&comment.group_id)
-                                {
-                                    let senders_read_lock = self.users.read().await;
-                                    let stomp_frame = StompFrame::new(&comment);
-
-                                    for recipient_id in distribution_group {
-                                        if let Some(sender) = senders_read_lock.get(recipient_id) {
-                                            let _ = sender.send(stomp_frame.clone());
-                                        }
-                                    }
-                                }
-                            }
-                            Err(err) => panic!("{:?}", err),
+            let msg = match self.consumer.recv().await {
+                Ok(msg) => msg,
+                Err(err) => {
+                    let delay = backoff.fail();
+                    if backoff.failures() >= config.max_failures {
+                        bail!("Unable to receive comments: {err}");
+                    }
+
+                    eprintln!("Unable to receive comments, retrying in {delay:?}: {err}");
+                    time::sleep(delay).await;
+                    continue;
+                }
+            };
+            backoff.succeed();
+
+            let comment = match rdkafka::Message::payload(&msg).map(Comment::decode) {
+                Some(Ok(comment)) => comment,
+                Some(Err(err)) => {
+                    eprintln!(
+                        "Skipping comment at offset {} of partition {}: {err}",
+                        rdkafka::Message::offset(&msg),
+                        rdkafka::Message::partition(&msg)
+                    );
+                    continue;
+                }
+                None => continue,
+            };
+
+            let metadata = rdkafka::Message::headers(&msg)
+                .map(stomp::record_metadata)
+                .unwrap_or_default();
+            self.distribute(&comment, &metadata).await;
+        }
+    }
+
+    /// Sends `comment` to all subscriptions of its group. Sending never blocks, only acks
+    /// of recipients whose subscription requires acknowledgement are locked, one at a time.
+    async fn distribute(&self, comment: &Comment, metadata: &SendMetadata<'_>) {
+        // frame is not built for comments nobody subscribed to
+        if !self.registry.has_subscribers(&comment.group_id) {
+            return;
+        }
+
+        let message_id = Uuid::new_v4().to_string();
+        // serialized once, deliveries only add their own headers
+        let frame = BroadcastFrame::new(&stomp::message_frame(comment, metadata, &message_id));
+        let comment_id: Arc<str> = comment.id.as_str().into();
+
+        self.registry
+            .with_group(&comment.group_id, |distribution_group| {
+                for (recipient_id, subscriptions) in distribution_group {
+                    let Some(connection) = self.connections.get(*recipient_id) else {
+                        continue;
+                    };
+
+                    for subscription in subscriptions {
+                        let mut delivery = Delivery {
+                            frame: frame.clone(),
+                            comment_id: comment_id.clone(),
+                            subscription: subscription.id.clone(),
+                            ack: None,
+                        };
+
+                        if subscription.ack == AckMode::Auto {
+                            let _ = connection.sender.send(delivery);
+                            continue;
                         }
+
+                        // each delivery gets its own ack id, as message id is shared by all recipients
+                        let ack = Uuid::new_v4().to_string();
+                        delivery.ack = Some(ack.clone());
+
+                        connection.acks.lock().unwrap().sent(
+                            subscription.ack,
+                            Unacknowledged {
+                                ack,
+                                subscription: subscription.id.clone(),
+                                frame: delivery.clone(),
+                                sent_at: Instant::now(),
+                                deliveries: 1,
+                            },
+                        );
+                        let _ = connection.sender.send(delivery);
                     }
                 }
-                Err(err) => panic!("{:?}", err),
+            });
+    }
+
+    /// Periodically redelivers messages that were not acknowledged in time. Client that keeps
+    /// ignoring a message receives ERROR once it was delivered `max_deliveries` times.
+    pub async fn redeliver_unacknowledged_blocking(&self) {
+        let mut interval = time::interval(ApplicationContext::REDELIVERY_CHECK_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            let now = Instant::now();
+
+            for connection in self.connections.all() {
+                let mut pending = connection.acks.lock().unwrap();
+                let expired = pending.expired(now, self.ack_config.timeout);
+
+                if let Err(err) = self.redeliver(&connection, &mut pending, expired) {
+                    let _ = connection
+                        .sender
+                        .send(StompFrame::error(&format!("{:#}", err)));
+                    // connection is closed after ERROR, nothing is redelivered anymore
+                    *pending = PendingAcks::default();
+                }
+            }
+        }
+    }
+
+    fn ack(&self, user_id: usize, ack: &str) -> Result<()> {
+        self.connection(user_id)?.acks.lock().unwrap().ack(ack)
+    }
+
+    fn nack(&self, user_id: usize, ack: &str) -> Result<()> {
+        let connection = self.connection(user_id)?;
+        let mut pending = connection.acks.lock().unwrap();
+
+        let rejected = pending.nack(ack)?;
+        self.redeliver(&connection, &mut pending, rejected)
+    }
+
+    fn connection(&self, user_id: usize) -> Result<Arc<Connection>> {
+        match self.connections.get(user_id) {
+            Some(connection) => Ok(connection),
+            None => bail!("User {user_id} was not added to context"),
+        }
+    }
+
+    fn redeliver(
+        &self,
+        connection: &Connection,
+        pending: &mut PendingAcks,
+        messages: Vec<Unacknowledged>,
+    ) -> Result<()> {
+        let now = Instant::now();
+
+        for message in messages {
+            if message.deliveries >= self.ack_config.max_deliveries {
+                bail!(
+                    "Message {} was not acknowledged after {} deliveries",
+                    message.ack,
+                    message.deliveries
+                );
             }
+
+            let _ = connection.sender.send(message.frame.clone());
+            pending.resent(message, now);
         }
+
+        Ok(())
     }
 
-    async fn subscribe(&self, user_id: usize, group: String) -> Result<()> {
-        if !self.users.read().await.contains_key(&user_id) {
+    async fn subscribe(
+        &self,
+        user_id: usize,
+        group: String,
+        subscription: String,
+        ack: AckMode,
+    ) -> Result<()> {
+        if !self.connections.contains(user_id) {
             bail!("Unable to register to group an user that was not added to context");
         }
 
-        self.distribution_map
-            .write()
-            .await
-            .entry(group)
-            .or_default()
-            .insert(user_id);
+        self.registry.subscribe(user_id, &group, &subscription, ack)
+    }
+
+    async fn unsubscribe(&self, user_id: usize, subscription: &str) -> Result<()> {
+        self.registry.unsubscribe(user_id, subscription)?;
+
+        if let Some(connection) = self.connections.get(user_id) {
+            connection.acks.lock().unwrap().unsubscribe(subscription);
+        }
 
         Ok(())
     }
 
-    async fn unsubscribe(&self, user_id: usize, group: String) -> Result<()> {
-        let mut distribution_map_write_lock = self.distribution_map.write().await;
+    async fn end_transaction(
+        &self,
+        user_id: usize,
+        transaction: &str,
+    ) -> Result<Vec<StompClientFrame<'static>>> {
+        self.transactions
+            .lock()
+            .await
+            .entry(user_id)
+            .or_insert_with(|| Transactions::new(self.transaction_limits))
+            .end(transaction)
+    }
+
+    /// Publishes comments of all SEND frames of a committed transaction at once, so consumers see
+    /// either all of them or none. ACK and NACK frames of the transaction are processed afterwards.
+    async fn commit(&self, user_id: usize, frames: Vec<StompClientFrame<'static>>) -> Result<()> {
+        let mut records = Vec::new();
+        let mut acknowledgements = Vec::new();
 
-        if let Some(distribution_group) = distribution_map_write_lock.get_mut(&group) {
-            distribution_group.remove(&user_id);
+        for frame in frames {
+            match frame {
+                StompClientFrame::SEND(send_frame) => {
+                    records.push(ApplicationContext::comment(send_frame).await?)
+                }
+                frame => acknowledgements.push(frame),
+            }
+        }
+
+        self.send_in_transaction(user_id, &records).await?;
+
+        for frame in acknowledgements {
+            match frame {
+                StompClientFrame::ACK(id) => self.ack(user_id, &id)?,
+                StompClientFrame::NACK(id) => self.nack(user_id, &id)?,
+                _ => bail!("Only SEND, ACK and NACK frames can be part of a transaction"),
+            }
         }
 
         Ok(())
     }
 
-    async fn send(&self, frame: SendClientFrame) -> Result<()> {
+    async fn send_in_transaction(
+        &self,
+        user_id: usize,
+        records: &[(Comment, OwnedHeaders)],
+    ) -> Result<()> {
+        if records.is_empty() {
+            return Ok(());
+        }
+
+        let producers = &self.transactional_producers;
+        let producer = producers[user_id % producers.len()].lock().await;
+        ApplicationContext::blocking(&producer, |producer| producer.begin_transaction()).await?;
+
+        let payloads: Vec<Vec<u8>> = records
+            .iter()
+            .map(|(comment, _)| comment.encode_to_vec())
+            .collect();
+        let deliveries = records
+            .iter()
+            .zip(&payloads)
+            .map(|((comment, headers), payload)| {
+                producer.send(
+                    FutureRecord::to(ApplicationContext::TOPIC)
+                        .payload(payload)
+                        .key(&comment.group_id)
+                        .headers(headers.clone()),
+                    Duration::from_secs(0),
+                )
+            });
+
+        let delivery_failure = future::join_all(deliveries)
+            .await
+            .into_iter()
+            .find_map(Result::err);
+
+        let result = match delivery_failure {
+            Some((err, _)) => Err(err.into()),
+            None => {
+                ApplicationContext::blocking(&producer, |producer| {
+                    producer.commit_transaction(ApplicationContext::TRANSACTION_TIMEOUT)
+                })
+                .await
+            }
+        };
+
+        if result.is_err() {
+            let _ = ApplicationContext::blocking(&producer, |producer| {
+                producer.abort_transaction(ApplicationContext::TRANSACTION_TIMEOUT)
+            })
+            .await;
+        }
+
+        result
+    }
+
+    /// Runs `call` of `producer` on the blocking thread pool. Beginning, committing and aborting
+    /// transactions block until brokers respond.
+    async fn blocking<T: Send + 'static>(
+        producer: &FutureProducer,
+        call: impl FnOnce(&FutureProducer) -> KafkaResult<T> + Send + 'static,
+    ) -> Result<T> {
+        let producer = producer.clone();
+        Ok(task::spawn_blocking(move || call(&producer)).await??)
+    }
+
+    /// Comment produced by `frame` together with Kafka record headers carrying frame's metadata.
+    async fn comment(frame: SendClientFrame<'_>) -> Result<(Comment, OwnedHeaders)> {
+        let mut metadata = frame.metadata().clone();
+
         let comment = match frame {
-            SendClientFrame::CREATE { destination, text } => Comment::new_create(destination, text),
-            SendClientFrame::UPDATE { id, text } => Comment::new_update(id, text).await?,
-            SendClientFrame::DELETE { id } => Comment::new_delete(id).await?,
+            SendClientFrame::CREATE {
+                destination, text, ..
+            } => match ApplicationContext::payload(&text, &mut metadata)? {
+                Some(payload) => Comment {
+                    parent_id: payload.parent_id,
+                    attachments: payload.attachments,
+                    ..Comment::new_create(destination.into_owned(), payload.text)
+                },
+                None => Comment::new_create(destination.into_owned(), text.into_owned()),
+            },
+            SendClientFrame::UPDATE { id, text, .. } => {
+                match ApplicationContext::payload(&text, &mut metadata)? {
+                    Some(payload) => {
+                        let mut comment =
+                            Comment::new_update(id.into_owned(), payload.text).await?;
+
+                        if payload.parent_id.is_some() && payload.parent_id != comment.parent_id {
+                            bail!("Comment {} can not be moved to another parent", comment.id);
+                        }
+
+                        comment.attachments = payload.attachments;
+                        comment
+                    }
+                    None => Comment::new_update(id.into_owned(), text.into_owned()).await?,
+                }
+            }
+            SendClientFrame::DELETE { id, .. } => Comment::new_delete(id.into_owned()).await?,
         };
 
+        Ok((comment, stomp::record_headers(&metadata)))
+    }
+
+    /// Reads JSON payload out of `text`, when `metadata` announces it by its `content-type`.
+    /// Nonce of the payload is passed on to subscribers as `client-message-id`.
+    fn payload(text: &str, metadata: &mut SendMetadata<'_>) -> Result<Option<CommentPayload>> {
+        if !CommentPayload::is_json(metadata.content_type.as_deref()) {
+            Comment::check_text(text)?;
+            return Ok(None);
+        }
+
+        let payload = CommentPayload::parse(text)?;
+        Comment::check_text(&payload.text)?;
+
+        match &metadata.client_message_id {
+            Some(client_message_id) if *client_message_id != payload.nonce => {
+                bail!("Payload nonce does not match client-message-id header")
+            }
+            _ => metadata.client_message_id = Some(payload.nonce.clone().into()),
+        }
+
+        Ok(Some(payload))
+    }
+
+    async fn send(&self, frame: SendClientFrame<'_>) -> Result<()> {
+        let (comment, headers) = ApplicationContext::comment(frame).await?;
+
         let sending_result = self
             .producer
             .send(
                 FutureRecord::to(ApplicationContext::TOPIC)
                     .payload(&comment.encode_to_vec())
-                    .key(&comment.group_id),
+                    .key(&comment.group_id)
+                    .headers(headers),
                 Duration::from_secs(0),
             )
             .await;
@@ -171,14 +564,24 @@ impl ApplicationContext {
 
         Ok(())
     }
+}
 
-    async fn remove_user_from_distribution_map(&self, user_id: usize) {
-        self.distribution_map
-            .write()
-            .await
-            .values_mut()
-            .for_each(|recipients| {
-                recipients.remove(&user_id);
-            });
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spectral::prelude::*;
+
+    #[tokio::test]
+    async fn transactional_calls_should_not_block_current_thread_runtime() {
+        let producer: FutureProducer = ClientConfig::new()
+            .set("bootstrap.servers", "localhost:9")
+            .create()
+            .unwrap();
+
+        // producer without transactional id fails without waiting for brokers
+        let result =
+            ApplicationContext::blocking(&producer, |producer| producer.begin_transaction()).await;
+
+        assert_that(&result).is_err();
     }
 }
@@ -0,0 +1,167 @@
+use std::{fmt::Display, time::Duration};
+
+use futures_util::{Sink, SinkExt, Stream, StreamExt};
+use tokio::time::{self, error::Elapsed, Instant};
+use warp::ws::Message;
+
+/// Peer is considered dead only after missing this many negotiated intervals,
+/// which leaves some slack for network latency.
+const INCOMING_TOLERANCE: u32 = 2;
+
+/// Forwards outgoing messages to the sink. When `interval` is negotiated and nothing was sent
+/// for that long, an EOL heart-beat is sent instead. Sink is closed once all messages are sent.
+pub async fn send_with_heart_beats<M, S>(mut messages: M, mut sink: S, interval: Option<Duration>)
+where
+    M: Stream<Item = Message> + Unpin,
+    S: Sink<Message> + Unpin,
+    S::Error: Display,
+{
+    let Some(interval) = interval else {
+        while let Some(message) = messages.next().await {
+            send(&mut sink, message).await;
+        }
+        let _ = sink.close().await;
+        return;
+    };
+
+    let mut heart_beat = time::interval_at(Instant::now() + interval, interval);
+
+    loop {
+        tokio::select! {
+            message = messages.next() => match message {
+                Some(message) => {
+                    send(&mut sink, message).await;
+                    heart_beat.reset();
+                }
+                None => break,
+            },
+            _ = heart_beat.tick() => send(&mut sink, Message::text("\n")).await,
+        }
+    }
+
+    let _ = sink.close().await;
+}
+
+/// Awaits next message from the peer. Fails when incoming heart-beating was negotiated and
+/// nothing (not even a heart-beat) arrived within tolerated time.
+pub async fn receive<S>(
+    stream: &mut S,
+    interval: Option<Duration>,
+) -> Result<Option<S::Item>, Elapsed>
+where
+    S: Stream + Unpin,
+{
+    match interval {
+        Some(interval) => time::timeout(interval * INCOMING_TOLERANCE, stream.next()).await,
+        None => Ok(stream.next().await),
+    }
+}
+
+async fn send<S>(sink: &mut S, message: Message)
+where
+    S: Sink<Message> + Unpin,
+    S::Error: Display,
+{
+    if let Err(e) = sink.send(message).await {
+        eprintln!("websocket send error: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::sink;
+    use spectral::prelude::*;
+    use std::convert::Infallible;
+    use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+    use tokio_stream::wrappers::UnboundedReceiverStream;
+
+    const INTERVAL: Duration = Duration::from_secs(10);
+
+    #[tokio::test(start_paused = true)]
+    async fn send_with_heart_beats_should_send_heart_beat_when_idle() {
+        let (_messages_tx, mut sent_rx) = spawn_sender(Some(INTERVAL));
+
+        time::sleep(INTERVAL + Duration::from_millis(1)).await;
+
+        assert_that(&sent_rx.try_recv())
+            .is_ok()
+            .is_equal_to(Message::text("\n"));
+        assert_that(&sent_rx.try_recv()).is_err();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn send_with_heart_beats_should_not_send_heart_beat_while_messages_are_sent() {
+        let (messages_tx, mut sent_rx) = spawn_sender(Some(INTERVAL));
+
+        for _ in 0..3 {
+            time::sleep(INTERVAL / 2).await;
+            messages_tx.send(Message::text("MESSAGE")).unwrap();
+            time::sleep(Duration::from_millis(1)).await;
+
+            assert_that(&sent_rx.try_recv())
+                .is_ok()
+                .is_equal_to(Message::text("MESSAGE"));
+        }
+
+        assert_that(&sent_rx.try_recv()).is_err();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn send_with_heart_beats_should_not_send_heart_beat_when_not_negotiated() {
+        let (_messages_tx, mut sent_rx) = spawn_sender(None);
+
+        time::sleep(INTERVAL * 10).await;
+
+        assert_that(&sent_rx.try_recv()).is_err();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn receive_should_fail_when_peer_is_silent_for_longer_than_tolerated() {
+        let (_tx, rx) = mpsc::unbounded_channel::<Message>();
+        let mut stream = UnboundedReceiverStream::new(rx);
+
+        let started = Instant::now();
+        let result = receive(&mut stream, Some(INTERVAL)).await;
+
+        assert_that(&result.is_err()).is_true();
+        assert_that(&started.elapsed()).is_equal_to(INTERVAL * INCOMING_TOLERANCE);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn receive_should_return_message_received_within_tolerated_time() {
+        let (tx, rx) = mpsc::unbounded_channel::<Message>();
+        let mut stream = UnboundedReceiverStream::new(rx);
+
+        tokio::spawn(async move {
+            time::sleep(INTERVAL * INCOMING_TOLERANCE - Duration::from_millis(1)).await;
+            tx.send(Message::text("\n")).unwrap();
+        });
+
+        let result = receive(&mut stream, Some(INTERVAL)).await;
+
+        assert_that(&result)
+            .is_ok()
+            .is_equal_to(Some(Message::text("\n")));
+    }
+
+    fn spawn_sender(
+        interval: Option<Duration>,
+    ) -> (UnboundedSender<Message>, UnboundedReceiver<Message>) {
+        let (messages_tx, messages_rx) = mpsc::unbounded_channel();
+        let (sent_tx, sent_rx) = mpsc::unbounded_channel();
+
+        let recording_sink = sink::unfold(sent_tx, |sent_tx, message| async move {
+            let _ = sent_tx.send(message);
+            Ok::<_, Infallible>(sent_tx)
+        });
+
+        tokio::spawn(send_with_heart_beats(
+            UnboundedReceiverStream::new(messages_rx),
+            Box::pin(recording_sink),
+            interval,
+        ));
+
+        (messages_tx, sent_rx)
+    }
+}
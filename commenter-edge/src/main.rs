@@ -1,36 +1,99 @@
+mod ack;
 mod comments;
+mod config;
+mod connection;
 mod context;
+mod heartbeat;
+mod outbound;
+mod payload;
+mod registry;
 mod stomp;
+mod subscription;
+mod supervisor;
+mod transaction;
 
+use commenter_stomp::{codec::StompDecoder, error::StompParseError};
+use config::{AckConfig, ConnectionConfig, RecoveryConfig};
 use context::ApplicationContext;
-use stomp::{StompClientFrame, StompFrame};
+use stomp::{ClientFrame, NegotiatedHeartBeat, StompClientFrame, StompFrame};
+use supervisor::Health;
 
-use std::{sync::Arc, net::{SocketAddr, IpAddr}};
-
-use futures_util::{SinkExt, StreamExt, TryFutureExt};
+use std::{
+    net::{IpAddr, SocketAddr},
+    process,
+    sync::Arc,
+};
 
-use tokio::sync::mpsc;
-use tokio_stream::wrappers::UnboundedReceiverStream;
+use futures_util::{future, stream::SplitStream, SinkExt, StreamExt};
 
 use warp::{
     self,
-    filters::ws::{WebSocket, Ws, Message},
+    filters::ws::{Message, WebSocket, Ws},
+    http::StatusCode,
     Filter,
 };
 
 use dotenv::dotenv;
 use std::env;
+use uuid::Uuid;
+
+const SERVER_NAME: &str = concat!("commenter-edge/", env!("CARGO_PKG_VERSION"));
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
     let broker_host = env::var("BROKER").expect("BROKER must be set");
+    let connection_config = ConnectionConfig::from_env();
+    let recovery_config = RecoveryConfig::from_env();
 
-    let context = Arc::new(ApplicationContext::new(&broker_host));
+    let context = match ApplicationContext::new(
+        &broker_host,
+        AckConfig::from_env(),
+        connection_config.transaction_limits,
+    ) {
+        Ok(context) => Arc::new(context),
+        Err(err) => {
+            eprintln!("Unable to connect to Kafka: {err:#}");
+            process::exit(1);
+        }
+    };
     let context_clone = context.clone();
+    let health = Arc::new(Health::default());
+    let health_clone = health.clone();
+    let redelivery_health = Arc::new(Health::default());
+    let redelivery_health_clone = redelivery_health.clone();
 
+    // consuming is restarted whenever it fails, otherwise connected clients would never receive anything
     tokio::task::spawn(async move {
-        context_clone.listen_blocking().await
+        supervisor::supervise(
+            "Comment consumer",
+            &health_clone,
+            recovery_config,
+            move || {
+                let context = context_clone.clone();
+                async move { context.listen(recovery_config).await }
+            },
+        )
+        .await
+    });
+
+    let context_clone = context.clone();
+
+    // without redelivery, messages that were not acknowledged would be tracked forever
+    tokio::task::spawn(async move {
+        supervisor::supervise(
+            "Redelivery",
+            &redelivery_health_clone,
+            recovery_config,
+            move || {
+                let context = context_clone.clone();
+                async move {
+                    context.redeliver_unacknowledged_blocking().await;
+                    Ok(())
+                }
+            },
+        )
+        .await
     });
 
     let context_filter_wrapper = warp::any().map(move || context.clone());
@@ -38,54 +101,229 @@ async fn main() {
     let ws_endpoint = warp::path("ws")
         .and(warp::ws())
         .and(context_filter_wrapper)
-        .map(|ws: Ws, context| ws.on_upgrade(move |socket| handle_connection(socket, context)));
+        .map(move |ws: Ws, context| {
+            ws.on_upgrade(move |socket| handle_connection(socket, context, connection_config))
+        });
 
-    let ip_address = env::var("WARP_ADDRESS").expect("WARP_ADDRESS must be set").parse::<IpAddr>().expect("WARP_ADDRESS is in ivalid format");
-    let port = env::var("WARP_PORT").expect("WARP_PORT has to be set").parse::<u16>().expect("WARP_PORT should be valud u16");
+    let health_endpoint = warp::path("health").and(warp::get()).map(move || {
+        if health.is_healthy() && redelivery_health.is_healthy() {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+    });
 
-    warp::serve(ws_endpoint).run(SocketAddr::new(ip_address, port)).await;
+    let ip_address = env::var("WARP_ADDRESS")
+        .expect("WARP_ADDRESS must be set")
+        .parse::<IpAddr>()
+        .expect("WARP_ADDRESS is in ivalid format");
+    let port = env::var("WARP_PORT")
+        .expect("WARP_PORT has to be set")
+        .parse::<u16>()
+        .expect("WARP_PORT should be valud u16");
+
+    warp::serve(ws_endpoint.or(health_endpoint))
+        .run(SocketAddr::new(ip_address, port))
+        .await;
 }
 
-async fn handle_connection(ws: WebSocket, context: Arc<ApplicationContext>) {
+async fn handle_connection(
+    ws: WebSocket,
+    context: Arc<ApplicationContext>,
+    config: ConnectionConfig,
+) {
     // Split user socket to receiving and producing parts
     let (mut user_ws_tx, mut user_ws_rx) = ws.split();
+    let mut decoder = StompDecoder::with_limits(config.limits);
 
-    // Create buffer channel for outgoing comments
-    let (mut tx, mut rx) = mpsc::unbounded_channel::<StompFrame>();
-    let mut rx = UnboundedReceiverStream::new(rx);
-
-    // Create async task that will listen for outgoing comments and push them to the websocket buffer
-    tokio::task::spawn(async move {
-        while let Some(comment) = rx.next().await {
-            user_ws_tx
-                .send(Message::text(comment))
-                .unwrap_or_else(|e| {
-                    eprintln!("websocket send error: {}", e);
-                })
-                .await;
+    // Client has to open with CONNECT (or STOMP) frame before sending anything else
+    let heart_beat = match accept_connection(&mut user_ws_rx, &mut decoder, &config).await {
+        Ok((connected_frame, heart_beat)) => {
+            if user_ws_tx
+                .send(Message::from(connected_frame))
+                .await
+                .is_err()
+            {
+                return;
+            }
+            heart_beat
+        }
+        Err(error_frame) => {
+            let _ = user_ws_tx.send(Message::from(error_frame)).await;
+            let _ = user_ws_tx.close().await;
+            return;
         }
+    };
+
+    // Create bounded buffer for outgoing comments, so a slow client can not exhaust memory
+    let (tx, rx) = outbound::channel(config.outbound_capacity, config.overflow_policy);
+    // Nothing is sent after ERROR frame, ending the stream closes the socket
+    let rx = rx.scan(false, |closed, frame| {
+        let message = (!*closed).then(|| {
+            *closed = frame.is_error();
+            Message::from(frame)
+        });
+        future::ready(message)
     });
 
-    // Register user to context in order to obtain ID
-    let user_id = context.add_user(tx).await;
-
-    // Loop for icoming messages from them socket
-    while let Some(result) = user_ws_rx.next().await {
-        if let Ok(msg) = result {
-            if let Ok(frame) = StompClientFrame::new(msg) {
-                if let StompClientFrame::DISCONNECT = frame {
-                    break; // wow... ugly as fuck...
-                } else if let Err(msg_handling_err) = context.handle_client_frame(user_id, frame).await {
-                    todo!("Handle msg handling errors: {:?}", msg_handling_err);
+    // Create async task that will listen for outgoing comments and push them to the websocket buffer,
+    // heart-beats are sent from here as well whenever there is nothing else to send
+    tokio::task::spawn(heartbeat::send_with_heart_beats(
+        rx,
+        user_ws_tx,
+        heart_beat.outgoing,
+    ));
+
+    // Register user to context in order to obtain ID, sender is kept to reply with receipts
+    let user_id = context.add_user(tx.clone()).await;
+
+    // Loop for icoming messages from them socket, single message can carry any number of frames
+    'connection: loop {
+        loop {
+            match decoder.next_frame() {
+                Ok(Some(ClientFrame {
+                    frame: StompClientFrame::DISCONNECT,
+                    receipt,
+                    ..
+                })) => {
+                    if let Some(receipt) = receipt {
+                        let _ = tx.send(StompFrame::receipt(&receipt));
+                    }
+                    break 'connection;
+                }
+                Ok(Some(ClientFrame {
+                    frame,
+                    receipt,
+                    transaction,
+                })) => {
+                    // Receipt is sent only once frame is fully processed (SEND is confirmed by Kafka)
+                    match context
+                        .handle_client_frame(user_id, frame, transaction.as_deref())
+                        .await
+                    {
+                        Ok(()) => {
+                            if let Some(receipt) = receipt {
+                                let _ = tx.send(StompFrame::receipt(&receipt));
+                            }
+                        }
+                        Err(msg_handling_err) => {
+                            let _ = tx.send(error_frame(
+                                &format!("{:#}", msg_handling_err),
+                                decoder.last_frame(),
+                                receipt.as_deref(),
+                            ));
+                            break 'connection;
+                        }
+                    }
+                }
+                Ok(None) => break,
+                Err(parsing_err) => {
+                    let _ = tx.send(parse_error_frame(&parsing_err, decoder.buffered()));
+                    break 'connection;
                 }
-            } else {
-                todo!("Handle parsing errors");
             }
-        } else {
-            todo!("Handle receiving errors");
+        }
+
+        // Client that stays silent for longer than negotiated is considered dead, the same goes for
+        // one whose outbound queue was closed as it could not keep up
+        let received = tokio::select! {
+            received = heartbeat::receive(&mut user_ws_rx, heart_beat.incoming) => received,
+            _ = tx.closed() => {
+                eprintln!("Closing connection of user {user_id} as nothing more can be sent to it");
+                break;
+            }
+        };
+        let Ok(result) = received else {
+            eprintln!("Closing connection of user {user_id} due to missing heart-beats");
             break;
+        };
+
+        match result {
+            Some(Ok(msg)) => {
+                if msg.is_close() {
+                    break;
+                }
+
+                // Only text and binary messages carry STOMP frames, pings are answered by warp itself
+                if msg.is_text() || msg.is_binary() {
+                    decoder.push(msg.as_bytes());
+                }
+            }
+            Some(Err(receiving_err)) => {
+                eprintln!("websocket receive error: {}", receiving_err);
+                break;
+            }
+            None => break,
         }
     }
 
+    if tx.dropped() > 0 {
+        eprintln!(
+            "Dropped {} frames of user {user_id} that could not keep up",
+            tx.dropped()
+        );
+    }
+
+    // Dropping the last sender lets the outgoing task flush pending frames (e.g. ERROR) and close the socket
     context.remove_user(user_id).await;
 }
+
+async fn accept_connection(
+    user_ws_rx: &mut SplitStream<WebSocket>,
+    decoder: &mut StompDecoder,
+    config: &ConnectionConfig,
+) -> Result<(StompFrame, NegotiatedHeartBeat), StompFrame> {
+    let connect_frame = loop {
+        match decoder.next_frame() {
+            Ok(Some(ClientFrame {
+                frame: StompClientFrame::CONNECT(frame) | StompClientFrame::STOMP(frame),
+                ..
+            })) => break frame,
+            Ok(Some(_)) => return Err(StompFrame::error("Expected CONNECT or STOMP frame")),
+            Err(err) => return Err(parse_error_frame(&err, decoder.buffered())),
+            Ok(None) => {}
+        }
+
+        match user_ws_rx.next().await {
+            Some(Ok(msg)) if msg.is_text() || msg.is_binary() => decoder.push(msg.as_bytes()),
+            Some(Ok(msg)) if !msg.is_close() => {}
+            _ => return Err(StompFrame::error("Connection closed before CONNECT frame")),
+        }
+    };
+
+    match connect_frame.negotiate_version() {
+        Some(version) => Ok((
+            StompFrame::connected(
+                version,
+                SERVER_NAME,
+                &Uuid::new_v4().to_string(),
+                &config.heart_beat,
+            ),
+            config.heart_beat.negotiate(&connect_frame.heart_beat),
+        )),
+        None => Err(StompFrame::unsupported_version()),
+    }
+}
+
+/// Builds ERROR frame for a frame that could not be processed. Per STOMP spec
+/// connection has to be closed right after ERROR frame is sent.
+fn error_frame(message: &str, excerpt: &[u8], receipt: Option<&str>) -> StompFrame {
+    let frame = StompFrame::error_with_excerpt(message, excerpt);
+
+    match receipt {
+        Some(receipt) => frame.with_receipt_id(receipt),
+        None => frame,
+    }
+}
+
+/// Builds ERROR frame for data that could not be parsed, receipt is unknown as frame was not read.
+fn parse_error_frame(err: &StompParseError, buffered: &[u8]) -> StompFrame {
+    match err {
+        // there is no frame to quote, only a prefix of data the client was not allowed to send
+        StompParseError::FrameTooLarge(_)
+        | StompParseError::TooManyHeaders(_)
+        | StompParseError::HeaderLineTooLong(_)
+        | StompParseError::BodyTooLarge(_) => StompFrame::error(&err.to_string()),
+        _ => error_frame(&err.to_string(), buffered, None),
+    }
+}
@@ -0,0 +1,485 @@
+use std::{
+    collections::VecDeque,
+    future::{self, Future},
+    pin::Pin,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+use anyhow::{bail, Error, Result};
+use futures_util::Stream;
+use warp::filters::ws::Message;
+
+use crate::stomp::{Delivery, StompFrame};
+
+/// What happens when a frame is sent to a connection whose outbound queue is full. Whatever
+/// the policy, the connection is closed with ERROR once there is no MESSAGE frame left to drop.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Oldest queued MESSAGE frame is dropped to make room for the new frame.
+    DropOldest,
+    /// Queued frames are dropped and the connection is closed with ERROR.
+    Disconnect,
+    /// Queued MESSAGE frame delivering the same comment to the same subscription is replaced
+    /// by the new one, so the client only gets the latest state. Oldest MESSAGE frame is dropped
+    /// when there is no such frame.
+    Coalesce,
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<OverflowPolicy> {
+        match value {
+            "drop-oldest" => Ok(OverflowPolicy::DropOldest),
+            "disconnect" => Ok(OverflowPolicy::Disconnect),
+            "coalesce" => Ok(OverflowPolicy::Coalesce),
+            _ => bail!("Unknown overflow policy {value}"),
+        }
+    }
+}
+
+/// Frame waiting in a connection's outbound queue.
+#[derive(Clone, Debug)]
+pub enum Outgoing {
+    Frame(StompFrame),
+    /// MESSAGE frame shared with other recipients of the same comment.
+    Message(Delivery),
+}
+
+impl Outgoing {
+    pub fn is_error(&self) -> bool {
+        matches!(self, Outgoing::Frame(frame) if frame.command == "ERROR")
+    }
+
+    fn is_message(&self) -> bool {
+        matches!(self, Outgoing::Message(_))
+    }
+}
+
+impl From<StompFrame> for Outgoing {
+    fn from(frame: StompFrame) -> Outgoing {
+        Outgoing::Frame(frame)
+    }
+}
+
+impl From<Delivery> for Outgoing {
+    fn from(delivery: Delivery) -> Outgoing {
+        Outgoing::Message(delivery)
+    }
+}
+
+impl From<Outgoing> for Message {
+    fn from(frame: Outgoing) -> Message {
+        match frame {
+            Outgoing::Frame(frame) => Message::from(frame),
+            Outgoing::Message(delivery) => Message::text(String::from(&delivery)),
+        }
+    }
+}
+
+/// Frame could not be queued, as the connection is closed or being closed.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Closed;
+
+/// Creates queue of frames waiting to be sent over a single connection, holding at most
+/// `capacity` frames. Receipts and errors are never dropped as client waits for them, they take
+/// place of a queued MESSAGE frame instead.
+pub fn channel(capacity: usize, policy: OverflowPolicy) -> (OutboundSender, OutboundReceiver) {
+    let shared = Arc::new(Mutex::new(Queue {
+        frames: VecDeque::with_capacity(capacity),
+        capacity,
+        policy,
+        senders: 1,
+        closed: false,
+        dropped: 0,
+        waker: None,
+        close_waker: None,
+    }));
+
+    (
+        OutboundSender {
+            shared: shared.clone(),
+        },
+        OutboundReceiver { shared },
+    )
+}
+
+#[derive(Debug)]
+struct Queue {
+    frames: VecDeque<Outgoing>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    senders: usize,
+    /// Set once the receiver is gone or the queue was closed with ERROR.
+    closed: bool,
+    dropped: u64,
+    waker: Option<Waker>,
+    /// Wakes up the task waiting for the queue to be closed.
+    close_waker: Option<Waker>,
+}
+
+impl Queue {
+    fn push(&mut self, frame: Outgoing) {
+        if self.frames.len() < self.capacity {
+            self.frames.push_back(frame);
+            return;
+        }
+
+        match self.policy {
+            OverflowPolicy::Disconnect => {
+                self.close_with_error();
+                return;
+            }
+            OverflowPolicy::Coalesce => {
+                if let Some(position) = self.coalescable(&frame) {
+                    self.frames[position] = frame;
+                    self.dropped += 1;
+                    return;
+                }
+            }
+            OverflowPolicy::DropOldest => {}
+        }
+
+        match self.frames.iter().position(Outgoing::is_message) {
+            Some(oldest) => {
+                self.frames.remove(oldest);
+                self.dropped += 1;
+                self.frames.push_back(frame);
+            }
+            // only frames that can not be dropped are queued, client does not read at all
+            None => self.close_with_error(),
+        }
+    }
+
+    /// Replaces queued frames with ERROR, nothing can be sent afterwards.
+    fn close_with_error(&mut self) {
+        self.dropped += self.frames.len() as u64 + 1;
+        self.frames.clear();
+        self.frames
+            .push_back(StompFrame::error("Connection is too slow to receive messages").into());
+        self.close();
+    }
+
+    fn close(&mut self) {
+        self.closed = true;
+
+        if let Some(waker) = self.close_waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Position of queued MESSAGE frame delivering the same comment to the same subscription as `frame`.
+    fn coalescable(&self, frame: &Outgoing) -> Option<usize> {
+        fn key(frame: &Outgoing) -> Option<(&str, &str)> {
+            match frame {
+                Outgoing::Message(delivery) => Some((&delivery.subscription, &delivery.comment_id)),
+                Outgoing::Frame(_) => None,
+            }
+        }
+
+        let delivered = key(frame)?;
+        self.frames
+            .iter()
+            .position(|queued| key(queued) == Some(delivered))
+    }
+
+    fn wake(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Sending half of a connection's outbound queue, it never blocks.
+#[derive(Debug)]
+pub struct OutboundSender {
+    shared: Arc<Mutex<Queue>>,
+}
+
+impl OutboundSender {
+    pub fn send(&self, frame: impl Into<Outgoing>) -> Result<(), Closed> {
+        let mut queue = self.shared.lock().unwrap();
+
+        if queue.closed {
+            return Err(Closed);
+        }
+
+        queue.push(frame.into());
+        queue.wake();
+        Ok(())
+    }
+
+    /// Completes once the queue is closed, either because the connection could not keep up or
+    /// because the receiver is gone. Only the last task waiting for it is woken up.
+    pub fn closed(&self) -> impl Future<Output = ()> + '_ {
+        future::poll_fn(|cx| {
+            let mut queue = self.shared.lock().unwrap();
+
+            if queue.closed {
+                return Poll::Ready(());
+            }
+
+            queue.close_waker = Some(cx.waker().clone());
+            Poll::Pending
+        })
+    }
+
+    /// Number of frames dropped so far because the connection could not keep up.
+    pub fn dropped(&self) -> u64 {
+        self.shared.lock().unwrap().dropped
+    }
+}
+
+impl Clone for OutboundSender {
+    fn clone(&self) -> OutboundSender {
+        self.shared.lock().unwrap().senders += 1;
+
+        OutboundSender {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl Drop for OutboundSender {
+    fn drop(&mut self) {
+        let mut queue = self.shared.lock().unwrap();
+        queue.senders -= 1;
+
+        if queue.senders == 0 {
+            queue.wake();
+        }
+    }
+}
+
+/// Receiving half of a connection's outbound queue. Stream ends once all senders are dropped
+/// and queued frames are taken out.
+#[derive(Debug)]
+pub struct OutboundReceiver {
+    shared: Arc<Mutex<Queue>>,
+}
+
+impl Stream for OutboundReceiver {
+    type Item = Outgoing;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Outgoing>> {
+        let mut queue = self.shared.lock().unwrap();
+
+        if let Some(frame) = queue.frames.pop_front() {
+            return Poll::Ready(Some(frame));
+        }
+
+        if queue.senders == 0 {
+            return Poll::Ready(None);
+        }
+
+        queue.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for OutboundReceiver {
+    fn drop(&mut self) {
+        let mut queue = self.shared.lock().unwrap();
+        queue.close();
+        queue.frames.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stomp::BroadcastFrame;
+    use futures_util::StreamExt;
+    use spectral::prelude::*;
+
+    #[tokio::test]
+    async fn receiver_should_end_once_all_senders_are_dropped() {
+        let (sender, receiver) = channel(2, OverflowPolicy::DropOldest);
+        let other_sender = sender.clone();
+
+        sender.send(StompFrame::receipt("1")).unwrap();
+        drop(sender);
+        other_sender.send(StompFrame::receipt("2")).unwrap();
+        drop(other_sender);
+
+        let frames = receiver.collect::<Vec<_>>().await;
+        assert_that(&receipts(&frames)).is_equal_to(vec!["1", "2"]);
+    }
+
+    #[tokio::test]
+    async fn drop_oldest_should_make_room_for_new_message() {
+        let (sender, receiver) = channel(2, OverflowPolicy::DropOldest);
+
+        for id in ["comment-1", "comment-2", "comment-3"] {
+            sender.send(message("sub-1", id, "CREATED")).unwrap();
+        }
+
+        assert_that(&sender.dropped()).is_equal_to(1);
+        drop(sender);
+        let frames = receiver.collect::<Vec<_>>().await;
+        assert_that(&comment_ids(&frames)).is_equal_to(vec!["comment-2", "comment-3"]);
+    }
+
+    #[tokio::test]
+    async fn full_queue_should_drop_message_to_make_room_for_receipt() {
+        let (sender, receiver) = channel(2, OverflowPolicy::DropOldest);
+
+        sender
+            .send(message("sub-1", "comment-1", "CREATED"))
+            .unwrap();
+        sender.send(StompFrame::receipt("1")).unwrap();
+        sender.send(StompFrame::receipt("2")).unwrap();
+
+        assert_that(&sender.dropped()).is_equal_to(1);
+        drop(sender);
+        let frames = receiver.collect::<Vec<_>>().await;
+        assert_that(&receipts(&frames)).is_equal_to(vec!["1", "2"]);
+    }
+
+    #[tokio::test]
+    async fn queue_full_of_receipts_should_be_closed_with_error() {
+        let (sender, receiver) = channel(2, OverflowPolicy::Coalesce);
+
+        for receipt in ["1", "2", "3"] {
+            sender.send(StompFrame::receipt(receipt)).unwrap();
+        }
+
+        assert_that(&sender.send(StompFrame::receipt("4"))).is_err_containing(Closed);
+        assert_that(&sender.dropped()).is_equal_to(3);
+        drop(sender);
+
+        let frames = receiver.collect::<Vec<_>>().await;
+        assert_that(&frames).has_length(1);
+        assert_that(&frames[0].is_error()).is_true();
+    }
+
+    #[tokio::test]
+    async fn closed_should_complete_once_queue_overflows() {
+        let (sender, _receiver) = channel(1, OverflowPolicy::Disconnect);
+        sender
+            .send(message("sub-1", "comment-1", "CREATED"))
+            .unwrap();
+
+        let closed = sender.closed();
+        tokio::pin!(closed);
+        assert_that(&futures_util::poll!(&mut closed).is_pending()).is_true();
+
+        sender
+            .send(message("sub-1", "comment-2", "CREATED"))
+            .unwrap();
+
+        closed.await;
+    }
+
+    #[tokio::test]
+    async fn disconnect_should_replace_queued_frames_with_error() {
+        let (sender, receiver) = channel(2, OverflowPolicy::Disconnect);
+
+        for id in ["comment-1", "comment-2", "comment-3"] {
+            sender.send(message("sub-1", id, "CREATED")).unwrap();
+        }
+
+        assert_that(&sender.send(StompFrame::receipt("1"))).is_err_containing(Closed);
+        assert_that(&sender.dropped()).is_equal_to(3);
+        drop(sender);
+
+        let frames = receiver.collect::<Vec<_>>().await;
+        assert_that(&frames).has_length(1);
+        assert_that(&frames[0].is_error()).is_true();
+    }
+
+    #[tokio::test]
+    async fn coalesce_should_replace_message_of_the_same_comment() {
+        let (sender, receiver) = channel(2, OverflowPolicy::Coalesce);
+
+        sender
+            .send(message("sub-1", "comment-1", "CREATED"))
+            .unwrap();
+        sender
+            .send(message("sub-1", "comment-2", "CREATED"))
+            .unwrap();
+        sender
+            .send(message("sub-1", "comment-1", "UPDATED"))
+            .unwrap();
+
+        assert_that(&sender.dropped()).is_equal_to(1);
+        drop(sender);
+
+        let frames = receiver.collect::<Vec<_>>().await;
+        assert_that(&comment_ids(&frames)).is_equal_to(vec!["comment-1", "comment-2"]);
+        assert_that(&String::from(
+            Message::from(frames[0].clone()).to_str().unwrap(),
+        ))
+        .contains("\naction:UPDATED\n");
+    }
+
+    #[tokio::test]
+    async fn coalesce_should_drop_oldest_message_of_other_comments() {
+        let (sender, receiver) = channel(2, OverflowPolicy::Coalesce);
+
+        sender
+            .send(message("sub-1", "comment-1", "CREATED"))
+            .unwrap();
+        sender
+            .send(message("sub-2", "comment-2", "CREATED"))
+            .unwrap();
+        sender
+            .send(message("sub-2", "comment-1", "UPDATED"))
+            .unwrap();
+
+        drop(sender);
+        let frames = receiver.collect::<Vec<_>>().await;
+        assert_that(&comment_ids(&frames)).is_equal_to(vec!["comment-2", "comment-1"]);
+    }
+
+    #[test]
+    fn sender_should_fail_once_receiver_is_dropped() {
+        let (sender, receiver) = channel(2, OverflowPolicy::DropOldest);
+
+        drop(receiver);
+
+        assert_that(&sender.send(StompFrame::receipt("1"))).is_err();
+    }
+
+    #[test]
+    fn overflow_policy_should_be_parsed() {
+        assert_that(&"coalesce".parse::<OverflowPolicy>())
+            .is_ok_containing(OverflowPolicy::Coalesce);
+        assert_that(&"drop-newest".parse::<OverflowPolicy>()).is_err();
+    }
+
+    fn message(subscription: &str, id: &str, action: &str) -> Delivery {
+        let frame = StompFrame::broadcast_message("article-42", "message-1", "text")
+            .with_header("id", id)
+            .with_header("action", action);
+
+        Delivery {
+            frame: BroadcastFrame::new(&frame),
+            comment_id: id.into(),
+            subscription: subscription.to_owned(),
+            ack: None,
+        }
+    }
+
+    fn comment_ids(frames: &[Outgoing]) -> Vec<&str> {
+        frames
+            .iter()
+            .map(|frame| match frame {
+                Outgoing::Message(delivery) => &*delivery.comment_id,
+                Outgoing::Frame(frame) => panic!("Expected MESSAGE, got {}", frame.command),
+            })
+            .collect()
+    }
+
+    fn receipts(frames: &[Outgoing]) -> Vec<&str> {
+        frames
+            .iter()
+            .map(|frame| match frame {
+                Outgoing::Frame(frame) => frame.header("receipt-id").unwrap(),
+                Outgoing::Message(_) => panic!("Expected RECEIPT, got MESSAGE"),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,115 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::comments::Comment;
+
+const JSON_CONTENT_TYPE: &str = "application/json";
+
+/// Body of a SEND frame with `content-type:application/json`, MESSAGE frames delivering comments
+/// sent this way carry the same structure.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct CommentPayload {
+    pub text: String,
+    /// Id of the comment this one replies to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<String>,
+    /// Chosen by the client, so it can recognize its own comment once it is delivered back.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub nonce: String,
+}
+
+impl CommentPayload {
+    /// Whether a body of `content_type` is a JSON payload rather than plain comment text.
+    pub fn is_json(content_type: Option<&str>) -> bool {
+        content_type.is_some_and(|content_type| {
+            content_type
+                .split(';')
+                .next()
+                .is_some_and(|media_type| media_type.trim() == JSON_CONTENT_TYPE)
+        })
+    }
+
+    pub fn parse(body: &str) -> Result<CommentPayload> {
+        serde_json::from_str(body).map_err(|err| anyhow!("Invalid comment payload: {err}"))
+    }
+
+    /// Payload describing `comment`, `nonce` is left out when empty.
+    pub fn of(comment: &Comment, nonce: &str) -> CommentPayload {
+        CommentPayload {
+            text: comment.text.clone(),
+            parent_id: comment.parent_id.clone(),
+            attachments: comment.attachments.clone(),
+            nonce: nonce.to_owned(),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Comment payload serialized")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spectral::prelude::*;
+
+    #[test]
+    fn is_json_should_ignore_media_type_parameters() {
+        assert_that(&CommentPayload::is_json(Some("application/json"))).is_true();
+        assert_that(&CommentPayload::is_json(Some(
+            "application/json; charset=utf-8",
+        )))
+        .is_true();
+        assert_that(&CommentPayload::is_json(Some("text/plain;charset=utf-8"))).is_false();
+        assert_that(&CommentPayload::is_json(None)).is_false();
+    }
+
+    #[test]
+    fn parse_should_read_optional_fields() {
+        let payload = CommentPayload::parse(
+            r#"{"text":"Agreed","parentId":"comment-1","attachments":["https://example.com/a.png"],"nonce":"local-7"}"#,
+        );
+
+        assert_that(&payload).is_ok_containing(CommentPayload {
+            text: "Agreed".to_owned(),
+            parent_id: Some("comment-1".to_owned()),
+            attachments: vec!["https://example.com/a.png".to_owned()],
+            nonce: "local-7".to_owned(),
+        });
+    }
+
+    #[test]
+    fn parse_should_default_missing_optional_fields() {
+        let payload = CommentPayload::parse(r#"{"text":"Agreed","nonce":"local-7"}"#).unwrap();
+
+        assert_that(&payload.parent_id).is_none();
+        assert_that(&payload.attachments).is_empty();
+    }
+
+    #[test]
+    fn parse_should_describe_schema_violation() {
+        let missing = CommentPayload::parse(r#"{"nonce":"local-7"}"#).unwrap_err();
+        let unknown =
+            CommentPayload::parse(r#"{"text":"a","nonce":"b","author":"c"}"#).unwrap_err();
+        let mistyped = CommentPayload::parse(r#"{"text":1,"nonce":"b"}"#).unwrap_err();
+
+        assert_that(&missing.to_string()).contains("missing field `text`");
+        assert_that(&unknown.to_string()).contains("unknown field `author`");
+        assert_that(&mistyped.to_string()).starts_with("Invalid comment payload: invalid type");
+    }
+
+    #[test]
+    fn to_json_should_leave_out_empty_fields() {
+        let payload = CommentPayload {
+            text: "Agreed".to_owned(),
+            parent_id: None,
+            attachments: vec![],
+            nonce: String::new(),
+        };
+
+        assert_that(&payload.to_json()).is_equal_to(r#"{"text":"Agreed"}"#.to_owned());
+    }
+}
@@ -0,0 +1,245 @@
+use std::{
+    collections::{hash_map::RandomState, HashMap},
+    hash::BuildHasher,
+    sync::{Mutex, RwLock},
+};
+
+use anyhow::{bail, Result};
+
+use crate::{stomp::AckMode, subscription::Subscriptions};
+
+/// Subscriptions of users subscribed to a destination, keyed by user id.
+pub type DistributionGroup = HashMap<usize, Vec<Subscription>>;
+
+#[derive(Debug)]
+pub struct Subscription {
+    pub id: String,
+    pub ack: AckMode,
+}
+
+/// Routes comments of a destination to its subscribers. Destinations are spread over shards,
+/// each behind its own lock, so subscribing to one destination does not hold up delivery to
+/// others. Subscriptions of each user are indexed as well, so a disconnect only visits
+/// destinations the user subscribed to. Destinations without subscribers are removed.
+///
+/// Locks are never held across `.await`, a user's shard is locked before a destination's shard.
+#[derive(Debug)]
+pub struct Registry {
+    destinations: Box<[RwLock<HashMap<String, DistributionGroup>>]>,
+    users: Box<[Mutex<HashMap<usize, Subscriptions>>]>,
+    hasher: RandomState,
+}
+
+impl Registry {
+    pub fn new(shards: usize) -> Registry {
+        assert!(shards > 0, "Registry needs at least one shard");
+
+        Registry {
+            destinations: (0..shards).map(|_| RwLock::default()).collect(),
+            users: (0..shards).map(|_| Mutex::default()).collect(),
+            hasher: RandomState::new(),
+        }
+    }
+
+    pub fn subscribe(
+        &self,
+        user_id: usize,
+        destination: &str,
+        subscription: &str,
+        ack: AckMode,
+    ) -> Result<()> {
+        let mut users_lock = self.user_shard(user_id).lock().unwrap();
+        users_lock
+            .entry(user_id)
+            .or_default()
+            .add(subscription, destination)?;
+
+        self.destination_shard(destination)
+            .write()
+            .unwrap()
+            .entry(destination.to_owned())
+            .or_default()
+            .entry(user_id)
+            .or_default()
+            .push(Subscription {
+                id: subscription.to_owned(),
+                ack,
+            });
+
+        Ok(())
+    }
+
+    pub fn unsubscribe(&self, user_id: usize, subscription: &str) -> Result<()> {
+        let mut users_lock = self.user_shard(user_id).lock().unwrap();
+        let Some(subscriptions) = users_lock.get_mut(&user_id) else {
+            bail!("Subscription {subscription} does not exist");
+        };
+        let destination = subscriptions.remove(subscription)?;
+
+        self.remove_from_destination(&destination, user_id, |subscriptions| {
+            subscriptions.retain(|other| other.id != subscription)
+        });
+
+        Ok(())
+    }
+
+    /// Cancels all subscriptions of `user_id`.
+    pub fn remove_user(&self, user_id: usize) {
+        let mut users_lock = self.user_shard(user_id).lock().unwrap();
+        let Some(subscriptions) = users_lock.remove(&user_id) else {
+            return;
+        };
+
+        for destination in subscriptions.destinations() {
+            self.remove_from_destination(destination, user_id, Vec::clear);
+        }
+    }
+
+    /// Calls `f` with subscribers of `destination`, unless there are none. Subscriptions of
+    /// the destination can not change until `f` returns, so it should not block.
+    pub fn with_group<R>(
+        &self,
+        destination: &str,
+        f: impl FnOnce(&DistributionGroup) -> R,
+    ) -> Option<R> {
+        self.destination_shard(destination)
+            .read()
+            .unwrap()
+            .get(destination)
+            .map(f)
+    }
+
+    pub fn has_subscribers(&self, destination: &str) -> bool {
+        self.destination_shard(destination)
+            .read()
+            .unwrap()
+            .contains_key(destination)
+    }
+
+    /// Applies `remove` to subscriptions of `user_id` to `destination`, dropping the user
+    /// and the destination once they are left without subscriptions.
+    fn remove_from_destination(
+        &self,
+        destination: &str,
+        user_id: usize,
+        remove: impl FnOnce(&mut Vec<Subscription>),
+    ) {
+        let mut destinations_lock = self.destination_shard(destination).write().unwrap();
+
+        if let Some(distribution_group) = destinations_lock.get_mut(destination) {
+            if let Some(subscriptions) = distribution_group.get_mut(&user_id) {
+                remove(subscriptions);
+
+                if subscriptions.is_empty() {
+                    distribution_group.remove(&user_id);
+                }
+            }
+
+            if distribution_group.is_empty() {
+                destinations_lock.remove(destination);
+            }
+        }
+    }
+
+    fn destination_shard(&self, destination: &str) -> &RwLock<HashMap<String, DistributionGroup>> {
+        let hash = self.hasher.hash_one(destination) as usize;
+        &self.destinations[hash % self.destinations.len()]
+    }
+
+    fn user_shard(&self, user_id: usize) -> &Mutex<HashMap<usize, Subscriptions>> {
+        &self.users[user_id % self.users.len()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spectral::prelude::*;
+
+    #[test]
+    fn registry_should_group_subscriptions_by_user() {
+        let registry = Registry::new(4);
+
+        registry
+            .subscribe(1, "article-42", "sub-1", AckMode::Auto)
+            .unwrap();
+        registry
+            .subscribe(1, "article-42", "sub-2", AckMode::Client)
+            .unwrap();
+        registry
+            .subscribe(2, "article-42", "sub-1", AckMode::Auto)
+            .unwrap();
+
+        let subscriptions = registry.with_group("article-42", |group| {
+            let mut subscriptions: Vec<(usize, usize)> = group
+                .iter()
+                .map(|(user_id, subscriptions)| (*user_id, subscriptions.len()))
+                .collect();
+            subscriptions.sort_unstable();
+            subscriptions
+        });
+        assert_that(&subscriptions)
+            .is_some()
+            .is_equal_to(vec![(1, 2), (2, 1)]);
+    }
+
+    #[test]
+    fn registry_should_reject_duplicate_subscription_id() {
+        let registry = Registry::new(4);
+        registry
+            .subscribe(1, "article-42", "sub-1", AckMode::Auto)
+            .unwrap();
+
+        assert_that(&registry.subscribe(1, "article-43", "sub-1", AckMode::Auto)).is_err();
+        assert_that(&registry.has_subscribers("article-43")).is_false();
+    }
+
+    #[test]
+    fn registry_should_remove_destination_once_last_subscription_is_cancelled() {
+        let registry = Registry::new(4);
+        registry
+            .subscribe(1, "article-42", "sub-1", AckMode::Auto)
+            .unwrap();
+        registry
+            .subscribe(1, "article-42", "sub-2", AckMode::Auto)
+            .unwrap();
+
+        registry.unsubscribe(1, "sub-1").unwrap();
+        assert_that(&registry.has_subscribers("article-42")).is_true();
+
+        registry.unsubscribe(1, "sub-2").unwrap();
+        assert_that(&registry.has_subscribers("article-42")).is_false();
+        assert_that(&registry.unsubscribe(1, "sub-2")).is_err();
+    }
+
+    #[test]
+    fn registry_should_not_track_user_without_subscriptions_on_unsubscribe() {
+        let registry = Registry::new(4);
+
+        assert_that(&registry.unsubscribe(1, "sub-1")).is_err();
+        assert_that(&registry.user_shard(1).lock().unwrap().contains_key(&1)).is_false();
+    }
+
+    #[test]
+    fn registry_should_cancel_all_subscriptions_of_removed_user() {
+        let registry = Registry::new(4);
+        registry
+            .subscribe(1, "article-42", "sub-1", AckMode::Auto)
+            .unwrap();
+        registry
+            .subscribe(1, "article-43", "sub-2", AckMode::Auto)
+            .unwrap();
+        registry
+            .subscribe(2, "article-43", "sub-1", AckMode::Auto)
+            .unwrap();
+
+        registry.remove_user(1);
+
+        assert_that(&registry.has_subscribers("article-42")).is_false();
+        assert_that(&registry.with_group("article-43", |group| group.contains_key(&1)))
+            .is_some()
+            .is_false();
+        // subscription ids are free to be used again
+        assert_that(&registry.subscribe(1, "article-42", "sub-1", AckMode::Auto)).is_ok();
+    }
+}
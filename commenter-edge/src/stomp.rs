@@ -0,0 +1,186 @@
+pub use commenter_stomp::stomp::*;
+
+use std::{borrow::Cow, str, sync::Arc};
+
+use rdkafka::message::{Header, Headers, OwnedHeaders};
+
+use crate::{
+    comments::{Comment, CommentState},
+    payload::CommentPayload,
+};
+
+/// MESSAGE frame delivering `comment` to all subscriptions of its group, `subscription` header
+/// is added to each [`Delivery`]. Besides the standard headers it carries comment's `id`,
+/// the `action` that produced it and `metadata` of the SEND frame it was created by. Comments sent
+/// as JSON payload are delivered as JSON payload as well.
+pub fn message_frame(
+    comment: &Comment,
+    metadata: &SendMetadata<'_>,
+    message_id: &str,
+) -> StompFrame {
+    let state: CommentState = num::FromPrimitive::from_i32(comment.state).unwrap();
+
+    let body = if CommentPayload::is_json(metadata.content_type.as_deref()) {
+        let nonce = metadata.client_message_id.as_deref().unwrap_or_default();
+        CommentPayload::of(comment, nonce).to_json()
+    } else {
+        comment.text.clone()
+    };
+
+    StompFrame::broadcast_message(&comment.group_id, message_id, &body)
+        .with_header("id", &comment.id)
+        .with_header("action", state.as_str_name())
+        .with_metadata(metadata)
+}
+
+/// MESSAGE frame serialized once for all recipients of a comment, delivered to a single subscription.
+#[derive(Clone, Debug)]
+pub struct Delivery {
+    pub frame: BroadcastFrame,
+    /// Id of the delivered comment.
+    pub comment_id: Arc<str>,
+    pub subscription: String,
+    /// Value of `ack` header, set for subscriptions that require acknowledgement.
+    pub ack: Option<String>,
+}
+
+/// Serializes the delivery into a string of its own, copying the shared frame once. warp sends
+/// only owned text messages, cost of the copy is measured by `broadcast_benchmarks` of the
+/// stomp crate.
+impl From<&Delivery> for String {
+    fn from(delivery: &Delivery) -> String {
+        match &delivery.ack {
+            Some(ack) => delivery
+                .frame
+                .to_string_with(&[("subscription", &delivery.subscription), ("ack", ack)]),
+            None => delivery
+                .frame
+                .to_string_with(&[("subscription", &delivery.subscription)]),
+        }
+    }
+}
+
+/// Kafka record headers carrying `metadata` of a SEND frame under the same names as in STOMP.
+pub fn record_headers(metadata: &SendMetadata<'_>) -> OwnedHeaders {
+    metadata
+        .headers()
+        // Kafka header names can not contain NUL
+        .filter(|(key, _)| !key.contains('\0'))
+        .fold(OwnedHeaders::new(), |headers, (key, value)| {
+            headers.insert(Header {
+                key,
+                value: Some(value),
+            })
+        })
+}
+
+/// Metadata of a SEND frame carried by Kafka record `headers`, headers that are not valid UTF-8
+/// are skipped.
+pub fn record_metadata<H: Headers>(headers: &H) -> SendMetadata<'_> {
+    SendMetadata::from_headers(headers.iter().filter_map(|header| {
+        let value = str::from_utf8(header.value?).ok()?;
+        Some((Cow::Borrowed(header.key), Cow::Borrowed(value)))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spectral::prelude::*;
+
+    #[test]
+    fn message_frame_should_carry_comment_headers() {
+        let comment = Comment {
+            id: "comment-1".to_owned(),
+            group_id: "article-42".to_owned(),
+            text: "Great read!".to_owned(),
+            state: CommentState::Updated as i32,
+            ..Comment::default()
+        };
+
+        let frame = message_frame(&comment, &SendMetadata::default(), "message-1");
+
+        assert_that(&frame.command.as_str()).is_equal_to("MESSAGE");
+        assert_that(&frame.header("destination")).is_equal_to(Some("article-42"));
+        assert_that(&frame.header("message-id")).is_equal_to(Some("message-1"));
+        assert_that(&frame.header("content-type")).is_equal_to(Some("text/plain;charset=utf-8"));
+        assert_that(&frame.header("id")).is_equal_to(Some("comment-1"));
+        assert_that(&frame.header("action")).is_equal_to(Some("UPDATED"));
+        assert_that(&frame.text.as_str()).is_equal_to("Great read!");
+    }
+
+    #[test]
+    fn message_frame_should_be_serialized_with_content_length() {
+        let comment = Comment {
+            id: "comment-1".to_owned(),
+            group_id: "article-42".to_owned(),
+            text: "zażółć".to_owned(),
+            state: CommentState::Created as i32,
+            ..Comment::default()
+        };
+
+        let frame: String = message_frame(&comment, &SendMetadata::default(), "message-1").into();
+
+        assert_that(&frame).contains("\ncontent-length:10\n");
+        assert_that(&frame).ends_with("\n\nzażółć\0");
+    }
+
+    #[test]
+    fn message_frame_should_carry_metadata_passed_through_kafka() {
+        let comment = Comment {
+            id: "comment-1".to_owned(),
+            group_id: "article-42".to_owned(),
+            text: "Agreed".to_owned(),
+            state: CommentState::Created as i32,
+            parent_id: Some("comment-0".to_owned()),
+            attachments: vec![],
+        };
+        let metadata = SendMetadata {
+            content_type: Some("application/json".into()),
+            client_message_id: Some("local-7".into()),
+            reply_to: Some("/queue/replies".into()),
+            custom: vec![("x-locale".into(), "en".into())],
+        };
+
+        let headers = record_headers(&metadata);
+        let frame = message_frame(&comment, &record_metadata(&headers), "message-1");
+
+        assert_that(&frame.header("content-type")).is_equal_to(Some("application/json"));
+        assert_that(&frame.header("client-message-id")).is_equal_to(Some("local-7"));
+        assert_that(&frame.header("reply-to")).is_equal_to(Some("/queue/replies"));
+        assert_that(&frame.header("x-locale")).is_equal_to(Some("en"));
+        assert_that(&frame.header("id")).is_equal_to(Some("comment-1"));
+        assert_that(&frame.text.as_str())
+            .is_equal_to(r#"{"text":"Agreed","parentId":"comment-0","nonce":"local-7"}"#);
+    }
+
+    #[test]
+    fn delivery_should_carry_subscription_and_ack() {
+        let comment = Comment {
+            id: "comment-1".to_owned(),
+            group_id: "article-42".to_owned(),
+            text: "Great read!".to_owned(),
+            state: CommentState::Created as i32,
+            ..Comment::default()
+        };
+        let delivery = Delivery {
+            frame: BroadcastFrame::new(&message_frame(
+                &comment,
+                &SendMetadata::default(),
+                "message-1",
+            )),
+            comment_id: comment.id.as_str().into(),
+            subscription: "sub-1".to_owned(),
+            ack: Some("ack-1".to_owned()),
+        };
+
+        let (frame, _) = StompFrame::decode(String::from(&delivery).as_bytes())
+            .unwrap()
+            .unwrap();
+
+        assert_that(&frame.header("subscription")).is_equal_to(Some("sub-1"));
+        assert_that(&frame.header("ack")).is_equal_to(Some("ack-1"));
+        assert_that(&frame.header("destination")).is_equal_to(Some("article-42"));
+        assert_that(&frame.text.as_str()).is_equal_to("Great read!");
+    }
+}
@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+
+/// Subscriptions held by a single connection, destination of each keyed by subscription id.
+/// Several subscriptions can share the same destination.
+#[derive(Default, Debug)]
+pub struct Subscriptions {
+    destinations: HashMap<String, String>,
+}
+
+impl Subscriptions {
+    pub fn add(&mut self, id: &str, destination: &str) -> Result<()> {
+        if self.destinations.contains_key(id) {
+            bail!("Subscription {id} already exists");
+        }
+
+        self.destinations
+            .insert(id.to_owned(), destination.to_owned());
+        Ok(())
+    }
+
+    /// Removes subscription `id`, returning its destination.
+    pub fn remove(&mut self, id: &str) -> Result<String> {
+        match self.destinations.remove(id) {
+            Some(destination) => Ok(destination),
+            None => bail!("Subscription {id} does not exist"),
+        }
+    }
+
+    /// Destinations subscribed to, each listed once.
+    pub fn destinations(&self) -> impl Iterator<Item = &str> {
+        let mut destinations: Vec<&str> = self.destinations.values().map(String::as_str).collect();
+        destinations.sort_unstable();
+        destinations.dedup();
+        destinations.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spectral::prelude::*;
+
+    #[test]
+    fn subscriptions_should_allow_several_subscriptions_to_the_same_destination() {
+        let mut subscriptions = Subscriptions::default();
+
+        assert_that(&subscriptions.add("sub-1", "article-42")).is_ok();
+        assert_that(&subscriptions.add("sub-2", "article-42")).is_ok();
+        assert_that(&subscriptions.add("sub-3", "article-43")).is_ok();
+
+        assert_that(&subscriptions.destinations().collect::<Vec<_>>())
+            .is_equal_to(vec!["article-42", "article-43"]);
+    }
+
+    #[test]
+    fn subscriptions_should_reject_duplicate_id() {
+        let mut subscriptions = Subscriptions::default();
+        subscriptions.add("sub-1", "article-42").unwrap();
+
+        assert_that(&subscriptions.add("sub-1", "article-43")).is_err();
+        assert_that(&subscriptions.remove("sub-1")).is_ok_containing("article-42".to_owned());
+    }
+
+    #[test]
+    fn subscriptions_should_reject_removal_of_unknown_id() {
+        let mut subscriptions = Subscriptions::default();
+        subscriptions.add("sub-1", "article-42").unwrap();
+
+        subscriptions.remove("sub-1").unwrap();
+
+        assert_that(&subscriptions.remove("sub-1")).is_err();
+        assert_that(&subscriptions.destinations().count()).is_equal_to(0);
+    }
+}
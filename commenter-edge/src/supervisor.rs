@@ -0,0 +1,141 @@
+use std::{
+    future::Future,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use anyhow::Result;
+use tokio::time;
+
+use commenter_recovery::Backoff;
+
+use crate::config::RecoveryConfig;
+
+/// Whether the instance does its job, reported by the health endpoint.
+#[derive(Debug)]
+pub struct Health {
+    healthy: AtomicBool,
+}
+
+impl Default for Health {
+    fn default() -> Health {
+        Health {
+            healthy: AtomicBool::new(true),
+        }
+    }
+}
+
+impl Health {
+    pub fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed)
+    }
+
+    fn set_healthy(&self, healthy: bool) {
+        self.healthy.store(healthy, Ordering::Relaxed);
+    }
+}
+
+/// Keeps task created by `start` running, it is restarted with backoff whenever it returns
+/// or panics. Instance is reported unhealthy once the task failed `max_failures` times in a row
+/// and healthy again once a restarted task keeps running for `stable_after`.
+pub async fn supervise<F, T>(name: &str, health: &Health, config: RecoveryConfig, mut start: F)
+where
+    F: FnMut() -> T,
+    T: Future<Output = Result<()>> + Send + 'static,
+{
+    let mut backoff = Backoff::new(&config);
+
+    loop {
+        let mut task = tokio::spawn(start());
+
+        let result = match time::timeout(config.stable_after, &mut task).await {
+            Ok(result) => result,
+            Err(_) => {
+                backoff.succeed();
+                health.set_healthy(true);
+                task.await
+            }
+        };
+
+        match result {
+            Ok(Ok(())) => eprintln!("{name} stopped"),
+            Ok(Err(err)) => eprintln!("{name} failed: {err:#}"),
+            Err(err) => eprintln!("{name} panicked: {err}"),
+        }
+
+        let delay = backoff.fail();
+        if backoff.failures() >= config.max_failures {
+            health.set_healthy(false);
+        }
+
+        eprintln!("Restarting {name} in {delay:?}");
+        time::sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::bail;
+    use spectral::prelude::*;
+    use std::sync::{atomic::AtomicU32, Arc};
+    use tokio::time::Duration;
+
+    #[tokio::test(start_paused = true)]
+    async fn supervise_should_restart_failed_and_panicked_task() {
+        let health = Health::default();
+        let starts = Arc::new(AtomicU32::new(0));
+        let starts_clone = starts.clone();
+
+        let _ = time::timeout(
+            Duration::from_millis(350),
+            supervise("Consumer", &health, config(), move || {
+                let start = starts_clone.fetch_add(1, Ordering::Relaxed);
+                async move {
+                    if start == 1 {
+                        panic!("Poisoned");
+                    }
+                    bail!("Broker unavailable");
+                }
+            }),
+        )
+        .await;
+
+        // restarted after 100 and another 200 milliseconds
+        assert_that(&starts.load(Ordering::Relaxed)).is_equal_to(3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn supervise_should_report_unhealthy_until_task_recovers() {
+        let health = Health::default();
+        let starts = Arc::new(AtomicU32::new(0));
+        let starts_clone = starts.clone();
+
+        let supervised = supervise("Consumer", &health, config(), move || {
+            let start = starts_clone.fetch_add(1, Ordering::Relaxed);
+            async move {
+                if start < 3 {
+                    bail!("Broker unavailable");
+                }
+                time::sleep(Duration::from_secs(3600)).await;
+                Ok(())
+            }
+        });
+        tokio::pin!(supervised);
+
+        let _ = time::timeout(Duration::from_millis(701), &mut supervised).await;
+        assert_that(&health.is_healthy()).is_false();
+
+        let _ = time::timeout(Duration::from_secs(1), &mut supervised).await;
+        assert_that(&health.is_healthy()).is_true();
+        assert_that(&starts.load(Ordering::Relaxed)).is_equal_to(4);
+    }
+
+    fn config() -> RecoveryConfig {
+        RecoveryConfig {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(500),
+            max_failures: 3,
+            stable_after: Duration::from_secs(1),
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+
+use crate::stomp::StompClientFrame;
+
+/// Limits of transactions started by a single connection, connection is closed with ERROR
+/// when exceeded.
+#[derive(Clone, Copy, Debug)]
+pub struct TransactionLimits {
+    /// Number of transactions that were started and neither committed nor aborted yet.
+    pub max_open: usize,
+    /// Number of frames buffered by a single transaction.
+    pub max_frames: usize,
+}
+
+/// Transactions started by a single connection, each buffering frames sent as its part
+/// until it is committed or aborted.
+#[derive(Debug)]
+pub struct Transactions {
+    active: HashMap<String, Vec<StompClientFrame<'static>>>,
+    limits: TransactionLimits,
+}
+
+impl Transactions {
+    pub fn new(limits: TransactionLimits) -> Transactions {
+        Transactions {
+            active: HashMap::new(),
+            limits,
+        }
+    }
+
+    pub fn begin(&mut self, transaction: &str) -> Result<()> {
+        if self.active.contains_key(transaction) {
+            bail!("Transaction {transaction} was already started");
+        }
+
+        if self.active.len() >= self.limits.max_open {
+            bail!(
+                "Too many open transactions, at most {} are allowed",
+                self.limits.max_open
+            );
+        }
+
+        self.active.insert(transaction.to_owned(), Vec::new());
+        Ok(())
+    }
+
+    /// Buffers `frame` as a part of `transaction`, it is processed only once the transaction is committed.
+    pub fn add(&mut self, transaction: &str, frame: StompClientFrame<'static>) -> Result<()> {
+        match self.active.get_mut(transaction) {
+            Some(frames) if frames.len() >= self.limits.max_frames => bail!(
+                "Transaction {transaction} has too many frames, at most {} are allowed",
+                self.limits.max_frames
+            ),
+            Some(frames) => {
+                frames.push(frame);
+                Ok(())
+            }
+            None => bail!("Transaction {transaction} was not started"),
+        }
+    }
+
+    /// Ends `transaction`, returning frames sent as its part in the order they were received.
+    pub fn end(&mut self, transaction: &str) -> Result<Vec<StompClientFrame<'static>>> {
+        match self.active.remove(transaction) {
+            Some(frames) => Ok(frames),
+            None => bail!("Transaction {transaction} was not started"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spectral::prelude::*;
+
+    #[test]
+    fn transactions_should_return_buffered_frames_in_order() {
+        let mut transactions = transactions();
+        transactions.begin("tx-1").unwrap();
+
+        transactions
+            .add("tx-1", StompClientFrame::ACK("ack-1".into()))
+            .unwrap();
+        transactions
+            .add("tx-1", StompClientFrame::NACK("ack-2".into()))
+            .unwrap();
+
+        assert_that(&transactions.end("tx-1")).is_ok_containing(vec![
+            StompClientFrame::ACK("ack-1".into()),
+            StompClientFrame::NACK("ack-2".into()),
+        ]);
+    }
+
+    #[test]
+    fn transactions_should_keep_concurrent_transactions_apart() {
+        let mut transactions = transactions();
+        transactions.begin("tx-1").unwrap();
+        transactions.begin("tx-2").unwrap();
+
+        transactions
+            .add("tx-2", StompClientFrame::ACK("ack-1".into()))
+            .unwrap();
+
+        assert_that(&transactions.end("tx-1")).is_ok_containing(vec![]);
+        assert_that(&transactions.end("tx-2")).is_ok().has_length(1);
+    }
+
+    #[test]
+    fn transactions_should_reject_transaction_started_twice() {
+        let mut transactions = transactions();
+        transactions.begin("tx-1").unwrap();
+
+        assert_that(&transactions.begin("tx-1")).is_err();
+    }
+
+    #[test]
+    fn transactions_should_reject_frames_of_unknown_transaction() {
+        let mut transactions = transactions();
+
+        assert_that(&transactions.add("tx-1", StompClientFrame::ACK("ack-1".into()))).is_err();
+        assert_that(&transactions.end("tx-1")).is_err();
+    }
+
+    #[test]
+    fn transactions_should_limit_open_transactions() {
+        let mut transactions = transactions();
+        transactions.begin("tx-1").unwrap();
+        transactions.begin("tx-2").unwrap();
+
+        assert_that(&transactions.begin("tx-3")).is_err();
+
+        transactions.end("tx-1").unwrap();
+        assert_that(&transactions.begin("tx-3")).is_ok();
+    }
+
+    #[test]
+    fn transactions_should_limit_frames_of_transaction() {
+        let mut transactions = transactions();
+        transactions.begin("tx-1").unwrap();
+
+        for ack in ["ack-1", "ack-2", "ack-3"] {
+            transactions
+                .add("tx-1", StompClientFrame::ACK(ack.into()))
+                .unwrap();
+        }
+
+        assert_that(&transactions.add("tx-1", StompClientFrame::ACK("ack-4".into()))).is_err();
+    }
+
+    #[test]
+    fn transactions_should_forget_ended_transaction() {
+        let mut transactions = transactions();
+        transactions.begin("tx-1").unwrap();
+
+        transactions.end("tx-1").unwrap();
+
+        assert_that(&transactions.end("tx-1")).is_err();
+    }
+
+    fn transactions() -> Transactions {
+        Transactions::new(TransactionLimits {
+            max_open: 2,
+            max_frames: 3,
+        })
+    }
+}
@@ -1,14 +1,17 @@
 use commenter_database::{comments::Comment, establish_connection};
+use commenter_recovery::{Backoff, RecoveryConfig};
 
 use diesel::{ExpressionMethods, RunQueryDsl};
 use prost::{DecodeError, Message};
 use thiserror::Error;
 
-use std::time::Duration;
+use std::{
+    process, thread,
+    time::{Duration, Instant},
+};
 
 use rdkafka::{
     consumer::{BaseConsumer, CommitMode, Consumer},
-    error::KafkaError,
     message::BorrowedMessage,
     ClientConfig,
 };
@@ -19,41 +22,120 @@ use std::env;
 const CONSUMER_GROUP_ID: &str = "commenter-hotstorage";
 const TOPIC: &str = "comments";
 
+/// Keeps consuming comments, consumer is restarted with backoff whenever it fails or panics.
+/// Process exits with failure once consumer can not recover, so it is restarted from scratch
+/// and the failure is visible to whoever runs it.
 fn main() {
     dotenv().ok();
     let broker_host = env::var("BROKER").expect("BROKER must be set");
+    let config = RecoveryConfig::from_env();
+    let mut restarts = Backoff::new(&config);
+
+    loop {
+        let started_at = Instant::now();
+        let broker_host = broker_host.clone();
+
+        match thread::spawn(move || consume(&broker_host, config)).join() {
+            Ok(Err(error)) => eprintln!("Consumer failed: {:?}", error),
+            Ok(Ok(())) => eprintln!("Consumer stopped"),
+            // panic message is already printed by the default hook
+            Err(_) => eprintln!("Consumer panicked"),
+        }
+
+        if started_at.elapsed() >= config.stable_after {
+            restarts.succeed();
+        }
 
+        let delay = restarts.fail();
+        if restarts.failures() > config.max_failures {
+            eprintln!(
+                "Consumer failed {} times in a row, giving up",
+                restarts.failures()
+            );
+            process::exit(1);
+        }
+
+        eprintln!("Restarting consumer in {:?}", delay);
+        thread::sleep(delay);
+    }
+}
+
+/// Stores consumed comments until failing `max_failures` times in a row. Offsets are committed
+/// only once the comment is stored, so a restarted consumer picks up where this one failed.
+fn consume(broker_host: &str, config: RecoveryConfig) -> Result<(), HotStorageError> {
     let consumer: BaseConsumer = ClientConfig::new()
         .set("group.id", CONSUMER_GROUP_ID)
-        .set("bootstrap.servers", &broker_host)
+        .set("bootstrap.servers", broker_host)
         .set("enable.partition.eof", "false")
         .set("session.timeout.ms", "10000")
         .set("enable.auto.commit", "false")
+        // comments published in a transaction are seen only once it is committed
+        .set("isolation.level", "read_committed")
         .set("allow.auto.create.topics", "true")
         .set("security.protocol", "PLAINTEXT")
-        .create()
-        .expect("Kafka consumer created");
+        .create()?;
 
-    consumer.subscribe(&[TOPIC]).expect("Subscribed to topic");
+    consumer.subscribe(&[TOPIC])?;
+    let mut backoff = Backoff::new(&config);
 
     loop {
-        if let Some(result) = consumer.poll(Duration::from_secs(1)) {
-            if let Err(error) = handle_message(&consumer, result) {
-                eprintln!("{:?}", error);
+        let message = match consumer.poll(Duration::from_secs(1)) {
+            Some(Ok(message)) => message,
+            Some(Err(error)) => {
+                wait_before_retry(&mut backoff, config.max_failures, error.into())?;
+                continue;
+            }
+            None => continue,
+        };
+
+        // the same message is retried, committing a later one would skip it for good
+        loop {
+            match handle_message(&consumer, &message) {
+                Ok(()) => break,
+                Err(error) if error.is_poison() => {
+                    // comment can never be stored, it is skipped so it does not block the partition
+                    eprintln!("Skipping comment: {:?}", error);
+                    break;
+                }
+                Err(error) => wait_before_retry(&mut backoff, config.max_failures, error)?,
             }
         }
+
+        backoff.succeed();
     }
 }
 
+/// Waits before another attempt, unless it already failed `max_failures` times in a row.
+fn wait_before_retry(
+    backoff: &mut Backoff,
+    max_failures: u32,
+    error: HotStorageError,
+) -> Result<(), HotStorageError> {
+    let delay = backoff.fail();
+    if backoff.failures() >= max_failures {
+        return Err(error);
+    }
+
+    eprintln!("{:?}, retrying in {:?}", error, delay);
+    thread::sleep(delay);
+    Ok(())
+}
+
 fn handle_message(
     consumer: &BaseConsumer,
-    message_result: Result<BorrowedMessage, KafkaError>,
+    message: &BorrowedMessage,
 ) -> Result<(), HotStorageError> {
-    let message = message_result?;
-    let comment = get_message_payload(&message)?;
+    let comment = match get_message_payload(message) {
+        Ok(comment) => comment,
+        Err(error) => {
+            consumer.commit_message(message, CommitMode::Sync)?;
+            return Err(error);
+        }
+    };
 
     let state = comment.state.clone();
     let text = comment.text.clone();
+    let attachments = comment.attachments.clone();
 
     //TODO: Add connection pool from r2d2
 
@@ -64,10 +146,11 @@ fn handle_message(
         .set((
             commenter_database::schema::comments::dsl::state.eq(state),
             commenter_database::schema::comments::dsl::text.eq(text),
+            commenter_database::schema::comments::dsl::attachments.eq(attachments),
         ))
         .execute(&mut establish_connection())?;
 
-    consumer.commit_message(&message, CommitMode::Sync)?;
+    consumer.commit_message(message, CommitMode::Sync)?;
     Ok(())
 }
 
@@ -94,3 +177,13 @@ pub enum HotStorageError {
     #[error("Received message is missing payload (offset: {0})")]
     MissingPayload(i64),
 }
+
+impl HotStorageError {
+    /// Whether the message that caused the error can never be stored, retrying it is pointless.
+    fn is_poison(&self) -> bool {
+        matches!(
+            self,
+            HotStorageError::Encoding(_) | HotStorageError::MissingPayload(_)
+        )
+    }
+}
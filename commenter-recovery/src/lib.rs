@@ -0,0 +1,127 @@
+use std::{env, time::Duration};
+
+const DEFAULT_RETRY_INITIAL_BACKOFF_MS: u32 = 100;
+const DEFAULT_RETRY_MAX_BACKOFF_MS: u32 = 30000;
+const DEFAULT_RETRY_MAX_FAILURES: u32 = 5;
+const DEFAULT_RECOVERED_AFTER_MS: u32 = 60000;
+
+/// Recovery of background tasks (e.g. Kafka consumers), shared by all services so they read
+/// the same variables the same way.
+#[derive(Clone, Copy, Debug)]
+pub struct RecoveryConfig {
+    /// Delay before the first retry, doubled with every consecutive failure up to `max_backoff`.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Consecutive failures after which a task is restarted, and consecutive restarts after
+    /// which the service is considered unable to recover.
+    pub max_failures: u32,
+    /// Restarted task that keeps running this long is considered recovered.
+    pub stable_after: Duration,
+}
+
+impl RecoveryConfig {
+    pub fn from_env() -> RecoveryConfig {
+        RecoveryConfig {
+            initial_backoff: Duration::from_millis(
+                env_or_default("RETRY_INITIAL_BACKOFF_MS", DEFAULT_RETRY_INITIAL_BACKOFF_MS).into(),
+            ),
+            max_backoff: Duration::from_millis(
+                env_or_default("RETRY_MAX_BACKOFF_MS", DEFAULT_RETRY_MAX_BACKOFF_MS).into(),
+            ),
+            max_failures: env_or_default("RETRY_MAX_FAILURES", DEFAULT_RETRY_MAX_FAILURES),
+            stable_after: Duration::from_millis(
+                env_or_default("RECOVERED_AFTER_MS", DEFAULT_RECOVERED_AFTER_MS).into(),
+            ),
+        }
+    }
+}
+
+/// Delay between consecutive attempts of a failing operation, doubled after every failure
+/// up to `max`.
+#[derive(Debug)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    next: Duration,
+    failures: u32,
+}
+
+impl Backoff {
+    pub fn new(config: &RecoveryConfig) -> Backoff {
+        Backoff {
+            initial: config.initial_backoff,
+            max: config.max_backoff,
+            next: config.initial_backoff,
+            failures: 0,
+        }
+    }
+
+    /// Records a failure, returning how long to wait before the next attempt.
+    pub fn fail(&mut self) -> Duration {
+        let delay = self.next;
+        self.next = self.next.saturating_mul(2).min(self.max);
+        self.failures += 1;
+        delay
+    }
+
+    /// Number of failures since the last success.
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    pub fn succeed(&mut self) {
+        self.next = self.initial;
+        self.failures = 0;
+    }
+}
+
+fn env_or_default(key: &str, default: u32) -> u32 {
+    match env::var(key) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|_| panic!("{key} should be valid u32")),
+        Err(_) => default,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spectral::prelude::*;
+
+    #[test]
+    fn backoff_should_double_delay_up_to_max() {
+        let mut backoff = Backoff::new(&config());
+
+        let delays: Vec<Duration> = (0..4).map(|_| backoff.fail()).collect();
+
+        assert_that(&delays).is_equal_to(vec![
+            Duration::from_millis(100),
+            Duration::from_millis(200),
+            Duration::from_millis(400),
+            Duration::from_millis(500),
+        ]);
+        assert_that(&backoff.failures()).is_equal_to(4);
+    }
+
+    #[test]
+    fn backoff_should_start_over_after_success() {
+        let mut backoff = Backoff::new(&config());
+        backoff.fail();
+        backoff.fail();
+
+        backoff.succeed();
+
+        assert_that(&backoff.failures()).is_equal_to(0);
+        assert_that(&backoff.fail()).is_equal_to(Duration::from_millis(100));
+    }
+
+    fn config() -> RecoveryConfig {
+        RecoveryConfig {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(500),
+            max_failures: 3,
+            stable_after: Duration::from_secs(1),
+        }
+    }
+}
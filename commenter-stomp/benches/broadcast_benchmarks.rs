@@ -0,0 +1,77 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use commenter_stomp::stomp::{BroadcastFrame, StompFrame};
+
+fn subscriptions(count: usize) -> Vec<String> {
+    (0..count).map(|index| format!("sub-{index}")).collect()
+}
+
+fn message_frame() -> StompFrame {
+    StompFrame::broadcast_message(
+        "article-42",
+        "5ba4c744-1d89-4b32-b2f6-5c7043e12d0b",
+        &"x".repeat(1024),
+    )
+    .with_header("id", "1d7a1f47-2b3e-4b8a-9a39-2f0e8c7d4c11")
+    .with_header("action", "CREATED")
+}
+
+fn bench_message_broadcast(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Message broadcast");
+
+    for count in [1_000, 10_000] {
+        let subscriptions = subscriptions(count);
+
+        group.throughput(Throughput::Elements(count as u64));
+
+        // whole frame built and serialized for every subscriber
+        group.bench_with_input(
+            BenchmarkId::new("frame per subscriber", count),
+            &subscriptions,
+            |bencher, subscriptions| {
+                let frame = message_frame();
+                bencher.iter(|| {
+                    for subscription in subscriptions {
+                        let delivery = frame.clone().with_header("subscription", subscription);
+                        black_box(String::from(delivery));
+                    }
+                })
+            },
+        );
+
+        // frame serialized once, only subscription header serialized for every subscriber
+        group.bench_with_input(
+            BenchmarkId::new("serialized once", count),
+            &subscriptions,
+            |bencher, subscriptions| {
+                let frame = message_frame();
+                bencher.iter(|| {
+                    let broadcast = BroadcastFrame::new(&frame);
+                    for subscription in subscriptions {
+                        black_box(broadcast.to_string_with(&[("subscription", subscription)]));
+                    }
+                })
+            },
+        );
+
+        // lower bound without the copy every text websocket message needs, frame is only shared
+        group.bench_with_input(
+            BenchmarkId::new("shared without copy", count),
+            &subscriptions,
+            |bencher, subscriptions| {
+                let frame = message_frame();
+                bencher.iter(|| {
+                    let broadcast = BroadcastFrame::new(&frame);
+                    for subscription in subscriptions {
+                        black_box((broadcast.clone(), subscription.clone()));
+                    }
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_message_broadcast);
+criterion_main!(benches);
@@ -1,7 +1,7 @@
-use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
 
-use commenter_stomp::stomp::StompClientFrame;
-use warp::filters::ws::Message;
+use bytes::BytesMut;
+use commenter_stomp::stomp::{StompClientFrame, StompFrame};
 
 const EOL_NEW_LINE: &str = "EOL=NewLine";
 const EOL_CARRIAGE_RETURN_WITH_NEW_LINE: &str = "EOL=CarriageReturnNewLine";
@@ -12,15 +12,15 @@ fn bench_stomp_client_frame_deserialization_disconnect(c: &mut Criterion) {
     // \n as EOL
     group.bench_with_input(
         BenchmarkId::from_parameter(EOL_NEW_LINE),
-        &Message::text("DISCONNECT\n\n\0"),
-        |bencher, input| bencher.iter(|| StompClientFrame::new(input)),
+        b"DISCONNECT\n\n\0",
+        |bencher, input| bencher.iter(|| StompClientFrame::parse(input)),
     );
 
     // \r\n as EOL
     group.bench_with_input(
         BenchmarkId::from_parameter(EOL_CARRIAGE_RETURN_WITH_NEW_LINE),
-        &Message::text("DISCONNECT\r\n\r\n\0"),
-        |bencher, input| bencher.iter(|| StompClientFrame::new(input)),
+        b"DISCONNECT\r\n\r\n\0",
+        |bencher, input| bencher.iter(|| StompClientFrame::parse(input)),
     );
 
     group.finish();
@@ -31,14 +31,14 @@ fn bench_stomp_client_frame_deserialization_subscribe(c: &mut Criterion) {
 
     group.bench_with_input(
         BenchmarkId::from_parameter(EOL_NEW_LINE),
-        &Message::text("SUBSCRIBE\ndestination:topic-1\nid:sub-1\n\n\0"),
-        |bencher, input| bencher.iter(|| StompClientFrame::new(input)),
+        b"SUBSCRIBE\ndestination:topic-1\nid:sub-1\n\n\0",
+        |bencher, input| bencher.iter(|| StompClientFrame::parse(input)),
     );
 
     group.bench_with_input(
         BenchmarkId::from_parameter(EOL_CARRIAGE_RETURN_WITH_NEW_LINE),
-        &Message::text("SUBSCRIBE\r\ndestination:topic-1\r\nid:sub-1\r\n\r\n\0"),
-        |bencher, input| bencher.iter(|| StompClientFrame::new(input)),
+        b"SUBSCRIBE\r\ndestination:topic-1\r\nid:sub-1\r\n\r\n\0",
+        |bencher, input| bencher.iter(|| StompClientFrame::parse(input)),
     );
 
     group.finish();
@@ -49,14 +49,14 @@ fn bench_stomp_client_frame_deserialization_unsubscribe(c: &mut Criterion) {
 
     group.bench_with_input(
         BenchmarkId::from_parameter(EOL_NEW_LINE),
-        &Message::text("UNSUBSCRIBE\nid:sub-1\n\n\0"),
-        |bencher, input| bencher.iter(|| StompClientFrame::new(input)),
+        b"UNSUBSCRIBE\nid:sub-1\n\n\0",
+        |bencher, input| bencher.iter(|| StompClientFrame::parse(input)),
     );
-    
+
     group.bench_with_input(
         BenchmarkId::from_parameter(EOL_CARRIAGE_RETURN_WITH_NEW_LINE),
-        &Message::text("UNSUBSCRIBE\r\nid:sub-1\r\n\r\n\0"),
-        |bencher, input| bencher.iter(|| StompClientFrame::new(input)),
+        b"UNSUBSCRIBE\r\nid:sub-1\r\n\r\n\0",
+        |bencher, input| bencher.iter(|| StompClientFrame::parse(input)),
     );
 
     group.finish();
@@ -64,17 +64,17 @@ fn bench_stomp_client_frame_deserialization_unsubscribe(c: &mut Criterion) {
 
 fn bench_stomp_client_frame_deserialization_send_create(c: &mut Criterion) {
     let mut group = c.benchmark_group("Send create frame deserialization");
-    
+
     group.bench_with_input(
         BenchmarkId::from_parameter(EOL_NEW_LINE),
-        &Message::text("SEND\naction:CREATE\ndestination:topic-1\n\npayload...payload..\0"),
-        |bencher, input| bencher.iter(|| StompClientFrame::new(input)),
+        b"SEND\naction:CREATE\ndestination:topic-1\n\npayload...payload..\0",
+        |bencher, input| bencher.iter(|| StompClientFrame::parse(input)),
     );
-    
+
     group.bench_with_input(
         BenchmarkId::from_parameter(EOL_CARRIAGE_RETURN_WITH_NEW_LINE),
-        &Message::text("SEND\r\naction:CREATE\r\ndestination:topic-1\r\n\r\npayload...payload..\0"),
-        |bencher, input| bencher.iter(|| StompClientFrame::new(input)),
+        b"SEND\r\naction:CREATE\r\ndestination:topic-1\r\n\r\npayload...payload..\0",
+        |bencher, input| bencher.iter(|| StompClientFrame::parse(input)),
     );
 
     group.finish();
@@ -82,17 +82,17 @@ fn bench_stomp_client_frame_deserialization_send_create(c: &mut Criterion) {
 
 fn bench_stomp_client_frame_deserialization_send_update(c: &mut Criterion) {
     let mut group = c.benchmark_group("Send update frame deserialization");
-    
+
     group.bench_with_input(
         BenchmarkId::from_parameter(EOL_NEW_LINE),
-        &Message::text("SEND\naction:UPDATE\nid:5ba4c744-1d89-4b32-b2f6-5c7043e12d0b\n\npayload...payload..\0"),
-        |bencher, input| bencher.iter(|| StompClientFrame::new(input)),
+        b"SEND\naction:UPDATE\nid:5ba4c744-1d89-4b32-b2f6-5c7043e12d0b\n\npayload...payload..\0",
+        |bencher, input| bencher.iter(|| StompClientFrame::parse(input)),
     );
-    
+
     group.bench_with_input(
         BenchmarkId::from_parameter(EOL_CARRIAGE_RETURN_WITH_NEW_LINE),
-        &Message::text("SEND\r\naction:UPDATE\r\nid:5ba4c744-1d89-4b32-b2f6-5c7043e12d0b\r\n\r\npayload...payload..\0"),
-        |bencher, input| bencher.iter(|| StompClientFrame::new(input)),
+        b"SEND\r\naction:UPDATE\r\nid:5ba4c744-1d89-4b32-b2f6-5c7043e12d0b\r\n\r\npayload...payload..\0",
+        |bencher, input| bencher.iter(|| StompClientFrame::parse(input)),
     );
 
     group.finish();
@@ -100,17 +100,164 @@ fn bench_stomp_client_frame_deserialization_send_update(c: &mut Criterion) {
 
 fn bench_stomp_client_frame_deserialization_send_delete(c: &mut Criterion) {
     let mut group = c.benchmark_group("Send delete frame deserialization");
-    
+
     group.bench_with_input(
         BenchmarkId::from_parameter(EOL_NEW_LINE),
-        &Message::text("SEND\naction:DELETE\nid:5ba4c744-1d89-4b32-b2f6-5c7043e12d0b\n\n\0"),
-        |bencher, input| bencher.iter(|| StompClientFrame::new(input)),
+        b"SEND\naction:DELETE\nid:5ba4c744-1d89-4b32-b2f6-5c7043e12d0b\n\n\0",
+        |bencher, input| bencher.iter(|| StompClientFrame::parse(input)),
     );
-    
+
     group.bench_with_input(
         BenchmarkId::from_parameter(EOL_CARRIAGE_RETURN_WITH_NEW_LINE),
-        &Message::text("SEND\r\naction:DELETE\r\nid:5ba4c744-1d89-4b32-b2f6-5c7043e12d0b\r\n\r\n\0"),
-        |bencher, input| bencher.iter(|| StompClientFrame::new(input)),
+        b"SEND\r\naction:DELETE\r\nid:5ba4c744-1d89-4b32-b2f6-5c7043e12d0b\r\n\r\n\0",
+        |bencher, input| bencher.iter(|| StompClientFrame::parse(input)),
+    );
+
+    group.finish();
+}
+
+fn bench_stomp_client_frame_deserialization_connect(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Connect frame deserialization");
+
+    group.bench_with_input(
+        BenchmarkId::new("CONNECT", EOL_NEW_LINE),
+        b"CONNECT\naccept-version:1.0,1.1,1.2\nhost:commenter\nheart-beat:10000,10000\n\n\0",
+        |bencher, input| bencher.iter(|| StompClientFrame::parse(input)),
+    );
+
+    group.bench_with_input(
+        BenchmarkId::new("STOMP", EOL_CARRIAGE_RETURN_WITH_NEW_LINE),
+        b"STOMP\r\naccept-version:1.0,1.1,1.2\r\nhost:commenter\r\nheart-beat:10000,10000\r\n\r\n\0",
+        |bencher, input| bencher.iter(|| StompClientFrame::parse(input)),
+    );
+
+    group.finish();
+}
+
+fn bench_stomp_client_frame_deserialization_escaped_headers(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Escaped headers deserialization");
+
+    group.bench_with_input(
+        BenchmarkId::from_parameter(EOL_NEW_LINE),
+        b"SUBSCRIBE\ndestination:article\\c42\\nreplies\nid:sub\\\\1\n\n\0",
+        |bencher, input| bencher.iter(|| StompClientFrame::parse(input)),
+    );
+
+    group.finish();
+}
+
+fn bench_stomp_client_frame_deserialization_large_body(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Large body deserialization");
+
+    for size in [1024, 64 * 1024, 1024 * 1024] {
+        let body = "x".repeat(size);
+
+        group.throughput(Throughput::Bytes(size as u64));
+
+        // body ends on first NUL octet
+        group.bench_with_input(
+            BenchmarkId::new("NUL terminated", size),
+            format!("SEND\naction:CREATE\ndestination:topic-1\n\n{body}\0").as_bytes(),
+            |bencher, input| bencher.iter(|| StompClientFrame::parse(input)),
+        );
+
+        // body length known upfront
+        group.bench_with_input(
+            BenchmarkId::new("content-length", size),
+            format!("SEND\naction:CREATE\ndestination:topic-1\ncontent-length:{size}\n\n{body}\0")
+                .as_bytes(),
+            |bencher, input| bencher.iter(|| StompClientFrame::parse(input)),
+        );
+    }
+
+    group.finish();
+}
+
+fn bench_stomp_client_frame_deserialization_owned(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Borrowed vs owned deserialization");
+    let body = "x".repeat(64 * 1024);
+    let input = format!("SEND\naction:CREATE\ndestination:topic-1\n\n{body}\0");
+
+    // frame borrowing from the input
+    group.bench_with_input(
+        BenchmarkId::from_parameter("borrowed"),
+        input.as_bytes(),
+        |bencher, input| bencher.iter(|| StompClientFrame::parse(input)),
+    );
+
+    // frame copied out of the input, as the edge has to do before the buffer is reused
+    group.bench_with_input(
+        BenchmarkId::from_parameter("owned"),
+        input.as_bytes(),
+        |bencher, input| {
+            bencher.iter(|| StompClientFrame::parse(input).map(StompClientFrame::into_owned))
+        },
+    );
+
+    group.finish();
+}
+
+fn message_frame(text: String) -> StompFrame {
+    StompFrame {
+        command: "MESSAGE".to_owned(),
+        headers: vec![
+            ("destination".to_owned(), "topic-1".to_owned()),
+            ("subscription".to_owned(), "sub-1".to_owned()),
+            (
+                "id".to_owned(),
+                "5ba4c744-1d89-4b32-b2f6-5c7043e12d0b".to_owned(),
+            ),
+            ("action".to_owned(), "CREATE".to_owned()),
+        ],
+        text,
+    }
+}
+
+fn bench_stomp_frame_serialization_message(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Message frame serialization");
+
+    for size in [16, 1024, 64 * 1024] {
+        let frame = message_frame("x".repeat(size));
+
+        group.throughput(Throughput::Bytes(size as u64));
+
+        // single buffer cleared and reused for every frame
+        group.bench_with_input(
+            BenchmarkId::new("reused buffer", size),
+            &frame,
+            |bencher, frame| {
+                let mut buffer = BytesMut::new();
+                bencher.iter(|| {
+                    buffer.clear();
+                    frame.encode(&mut buffer);
+                })
+            },
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("String", size),
+            &frame,
+            |bencher, frame| bencher.iter(|| String::from(frame.clone())),
+        );
+    }
+
+    group.finish();
+}
+
+fn bench_stomp_frame_serialization_escaped_headers(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Escaped headers serialization");
+    let frame = StompFrame::error("Unable to parse header line destination:article\\t42\r\n");
+
+    group.bench_with_input(
+        BenchmarkId::from_parameter("ERROR"),
+        &frame,
+        |bencher, frame| {
+            let mut buffer = BytesMut::new();
+            bencher.iter(|| {
+                buffer.clear();
+                frame.encode(&mut buffer);
+            })
+        },
     );
 
     group.finish();
@@ -118,12 +265,18 @@ fn bench_stomp_client_frame_deserialization_send_delete(c: &mut Criterion) {
 
 criterion_group!(
     benches,
+    bench_stomp_client_frame_deserialization_connect,
     bench_stomp_client_frame_deserialization_disconnect,
     bench_stomp_client_frame_deserialization_subscribe,
     bench_stomp_client_frame_deserialization_unsubscribe,
     bench_stomp_client_frame_deserialization_send_create,
     bench_stomp_client_frame_deserialization_send_update,
-    bench_stomp_client_frame_deserialization_send_delete
+    bench_stomp_client_frame_deserialization_send_delete,
+    bench_stomp_client_frame_deserialization_escaped_headers,
+    bench_stomp_client_frame_deserialization_large_body,
+    bench_stomp_client_frame_deserialization_owned,
+    bench_stomp_frame_serialization_message,
+    bench_stomp_frame_serialization_escaped_headers
 );
 
 criterion_main!(benches);
@@ -0,0 +1,29 @@
+#![no_main]
+
+use commenter_stomp::codec::StompDecoder;
+use libfuzzer_sys::fuzz_target;
+
+// First byte picks the chunk size, frames decoded from chunks have to match
+// frames decoded from the whole data at once
+fuzz_target!(|data: &[u8]| {
+    let Some((&chunk_size, data)) = data.split_first() else {
+        return;
+    };
+
+    let Ok(expected) = StompDecoder::new().decode_chunk(data) else {
+        return;
+    };
+
+    let mut decoder = StompDecoder::new();
+    let mut frames = Vec::new();
+
+    for chunk in data.chunks(usize::from(chunk_size.max(1))) {
+        frames.extend(
+            decoder
+                .decode_chunk(chunk)
+                .expect("Chunk of valid data is decoded"),
+        );
+    }
+
+    assert_eq!(frames, expected);
+});
@@ -0,0 +1,25 @@
+#![no_main]
+
+use commenter_stomp::stomp::StompFrame;
+use libfuzzer_sys::fuzz_target;
+
+// Decoded server frame has to serialize into data that decodes to the same frame
+fuzz_target!(|data: &[u8]| {
+    let Ok(Some((frame, _))) = StompFrame::decode(data) else {
+        return;
+    };
+
+    // commands are plain words, anything else (e.g. a lone CR) does not survive serialization
+    if !frame.command.bytes().all(|byte| byte.is_ascii_alphabetic()) {
+        return;
+    }
+
+    let mut raw = Vec::new();
+    frame.encode(&mut raw);
+
+    let (decoded, _) = StompFrame::decode(&raw)
+        .expect("Serialized frame is valid")
+        .expect("Serialized frame is complete");
+    assert_eq!(decoded.command, frame.command);
+    assert_eq!(decoded.text, frame.text);
+});
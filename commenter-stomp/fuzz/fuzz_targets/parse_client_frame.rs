@@ -0,0 +1,17 @@
+#![no_main]
+
+use commenter_stomp::stomp::{ParserLimits, StompClientFrame};
+use libfuzzer_sys::fuzz_target;
+
+// Parser has to reject malformed data with an error, never panic
+fuzz_target!(|data: &[u8]| {
+    let _ = StompClientFrame::parse(data);
+
+    let limits = ParserLimits {
+        max_frame_length: 512,
+        max_headers: 4,
+        max_header_line_length: 64,
+        max_body_length: 128,
+    };
+    let _ = StompClientFrame::decode_with_limits(data, &limits);
+});
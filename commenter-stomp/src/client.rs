@@ -0,0 +1,601 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+};
+
+use bytes::{Buf, BytesMut};
+use futures_util::{
+    stream::{SplitSink, SplitStream},
+    SinkExt, Stream, StreamExt,
+};
+use tokio::{
+    net::TcpStream,
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+};
+use tokio_tungstenite::{
+    tungstenite::{client::IntoClientRequest, Message},
+    MaybeTlsStream, WebSocketStream,
+};
+
+use crate::error::StompClientError;
+use crate::stomp::{
+    leading_eols, AckMode, ClientFrame, ConnectClientFrame, HeartBeat, SendClientFrame,
+    SendMetadata, StompClientFrame, StompFrame, MESSAGE, RECEIPT_ID, SUBSCRIPTION,
+    SUPPORTED_VERSIONS,
+};
+
+type Result<T> = std::result::Result<T, StompClientError>;
+type WebSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Async STOMP client talking to the edge over websocket. Every request asks for a receipt and
+/// completes only once the server confirmed it, e.g. `send_create` returns after the comment
+/// was published. ERROR frame fails all pending requests and closes the connection.
+///
+/// # Examples
+/// ```no_run
+/// use commenter_stomp::client::StompClient;
+/// use futures_util::StreamExt;
+///
+/// # async fn run() -> Result<(), commenter_stomp::error::StompClientError> {
+/// let client = StompClient::connect("ws://127.0.0.1:5060/ws").await?;
+///
+/// let mut comments = client.subscribe("article-42").await?;
+/// client.send_create("article-42", "Great read!").await?;
+///
+/// let message = comments.next().await.unwrap();
+/// assert_eq!(message.text, "Great read!");
+///
+/// client.disconnect().await
+/// # }
+/// ```
+pub struct StompClient {
+    sink: tokio::sync::Mutex<SplitSink<WebSocket, Message>>,
+    state: Arc<Mutex<State>>,
+    next_id: AtomicUsize,
+    reader: JoinHandle<()>,
+}
+
+/// MESSAGE frames delivered to a single subscription, ends once the connection is closed.
+pub struct Subscription {
+    id: String,
+    messages: mpsc::UnboundedReceiver<StompFrame>,
+}
+
+impl Subscription {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+impl Stream for Subscription {
+    type Item = StompFrame;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<StompFrame>> {
+        self.messages.poll_recv(cx)
+    }
+}
+
+impl StompClient {
+    /// Opens websocket connection to `url` (e.g. `ws://127.0.0.1:5060/ws`) and performs
+    /// CONNECT handshake. Heart-beating is not requested from the server.
+    pub async fn connect(url: &str) -> Result<StompClient> {
+        let request = url.into_client_request()?;
+        let host = request.uri().host().unwrap_or_default().to_owned();
+
+        let (ws, _) = tokio_tungstenite::connect_async(request).await?;
+        let (mut sink, stream) = ws.split();
+        let mut reader = FrameReader {
+            stream,
+            buffer: BytesMut::new(),
+        };
+
+        let connect_frame = StompClientFrame::CONNECT(ConnectClientFrame {
+            accept_version: SUPPORTED_VERSIONS
+                .iter()
+                .copied()
+                .map(Cow::Borrowed)
+                .collect(),
+            host: Some(host.into()),
+            login: None,
+            passcode: None,
+            heart_beat: HeartBeat::default(),
+        });
+        sink.send(Message::from(StompFrame::from(ClientFrame::from(
+            connect_frame,
+        ))))
+        .await?;
+
+        let Some(frame) = reader.next().await? else {
+            return Err(StompClientError::Closed);
+        };
+
+        match frame.command.as_str() {
+            "CONNECTED" => {}
+            "ERROR" => return Err(StompClientError::Server(error_message(&frame))),
+            command => return Err(StompClientError::UnexpectedFrame(command.to_owned())),
+        }
+
+        let state = Arc::new(Mutex::new(State::default()));
+        let reader = tokio::spawn(read(reader, state.clone()));
+
+        Ok(StompClient {
+            sink: tokio::sync::Mutex::new(sink),
+            state,
+            next_id: AtomicUsize::new(1),
+            reader,
+        })
+    }
+
+    /// Subscribes to comments of `destination`.
+    pub async fn subscribe(&self, destination: &str) -> Result<Subscription> {
+        let id = format!("sub-{}", self.next_id.fetch_add(1, Ordering::Relaxed));
+        let (sender, messages) = mpsc::unbounded_channel();
+
+        // registered upfront, messages can arrive right after the receipt
+        self.state
+            .lock()
+            .unwrap()
+            .subscriptions
+            .insert(id.clone(), sender);
+
+        let frame = StompClientFrame::SUBSCRIBE {
+            destination: destination.into(),
+            id: id.as_str().into(),
+            ack: AckMode::Auto,
+        };
+
+        if let Err(err) = self.request(frame).await {
+            self.state.lock().unwrap().subscriptions.remove(&id);
+            return Err(err);
+        }
+
+        Ok(Subscription { id, messages })
+    }
+
+    pub async fn unsubscribe(&self, subscription: Subscription) -> Result<()> {
+        self.state
+            .lock()
+            .unwrap()
+            .subscriptions
+            .remove(&subscription.id);
+
+        self.request(StompClientFrame::UNSUBSCRIBE(subscription.id.into()))
+            .await
+    }
+
+    pub async fn send_create(&self, destination: &str, text: &str) -> Result<()> {
+        self.send(SendClientFrame::CREATE {
+            destination: destination.into(),
+            text: text.into(),
+            metadata: SendMetadata::default(),
+        })
+        .await
+    }
+
+    pub async fn send_update(&self, id: &str, text: &str) -> Result<()> {
+        self.send(SendClientFrame::UPDATE {
+            id: id.into(),
+            text: text.into(),
+            metadata: SendMetadata::default(),
+        })
+        .await
+    }
+
+    pub async fn send_delete(&self, id: &str) -> Result<()> {
+        self.send(SendClientFrame::DELETE {
+            id: id.into(),
+            metadata: SendMetadata::default(),
+        })
+        .await
+    }
+
+    /// Sends SEND frame together with its metadata, which the edge echoes back on MESSAGE frames
+    /// delivering the comment.
+    pub async fn send(&self, frame: SendClientFrame<'_>) -> Result<()> {
+        self.request(StompClientFrame::SEND(frame)).await
+    }
+
+    /// Sends DISCONNECT and closes the websocket once the server confirmed that it
+    /// processed all previously sent frames.
+    pub async fn disconnect(self) -> Result<()> {
+        self.request(StompClientFrame::DISCONNECT).await?;
+        self.sink.lock().await.close().await?;
+        Ok(())
+    }
+
+    async fn request(&self, frame: StompClientFrame<'_>) -> Result<()> {
+        let receipt = format!("receipt-{}", self.next_id.fetch_add(1, Ordering::Relaxed));
+        let (sender, confirmation) = oneshot::channel();
+
+        self.state
+            .lock()
+            .unwrap()
+            .expect_receipt(receipt.clone(), sender)?;
+
+        let frame = ClientFrame {
+            frame,
+            receipt: Some(receipt.as_str().into()),
+            transaction: None,
+        };
+        let sent = self
+            .sink
+            .lock()
+            .await
+            .send(Message::from(StompFrame::from(frame)))
+            .await;
+
+        if let Err(err) = sent {
+            // frame never reached the server, nothing is going to confirm it
+            self.state.lock().unwrap().receipts.remove(&receipt);
+            return Err(err.into());
+        }
+
+        confirmation.await.unwrap_or(Err(StompClientError::Closed))
+    }
+}
+
+impl Drop for StompClient {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+#[derive(Default)]
+struct State {
+    connection: Connection,
+    subscriptions: HashMap<String, mpsc::UnboundedSender<StompFrame>>,
+    receipts: HashMap<String, oneshot::Sender<Result<()>>>,
+}
+
+#[derive(Default)]
+enum Connection {
+    #[default]
+    Open,
+    Closed,
+    /// Closed by ERROR frame carrying this message.
+    Failed(String),
+}
+
+impl Connection {
+    fn error(&self) -> Option<StompClientError> {
+        match self {
+            Connection::Open => None,
+            Connection::Closed => Some(StompClientError::Closed),
+            Connection::Failed(message) => Some(StompClientError::Server(message.clone())),
+        }
+    }
+}
+
+impl State {
+    fn expect_receipt(
+        &mut self,
+        receipt: String,
+        sender: oneshot::Sender<Result<()>>,
+    ) -> Result<()> {
+        if let Some(err) = self.connection.error() {
+            return Err(err);
+        }
+
+        self.receipts.insert(receipt, sender);
+        Ok(())
+    }
+
+    fn dispatch(&mut self, frame: StompFrame) {
+        match frame.command.as_str() {
+            "MESSAGE" => {
+                let subscription = frame.header(SUBSCRIPTION).map(str::to_owned);
+
+                if let Some(sender) = subscription.and_then(|id| self.subscriptions.get(&id)) {
+                    let _ = sender.send(frame);
+                }
+            }
+            "RECEIPT" => {
+                if let Some(sender) = frame
+                    .header(RECEIPT_ID)
+                    .and_then(|id| self.receipts.remove(id))
+                {
+                    let _ = sender.send(Ok(()));
+                }
+            }
+            "ERROR" => self.close(Connection::Failed(error_message(&frame))),
+            _ => {}
+        }
+    }
+
+    /// Fails pending requests and ends subscription streams.
+    fn close(&mut self, connection: Connection) {
+        for (_, sender) in self.receipts.drain() {
+            let _ = sender.send(Err(connection.error().unwrap_or(StompClientError::Closed)));
+        }
+
+        self.subscriptions.clear();
+        self.connection = connection;
+    }
+}
+
+/// Decodes server frames out of websocket messages, single message can carry any number of frames.
+struct FrameReader {
+    stream: SplitStream<WebSocket>,
+    buffer: BytesMut,
+}
+
+impl FrameReader {
+    /// Next frame sent by the server, `None` once the connection is closed.
+    async fn next(&mut self) -> Result<Option<StompFrame>> {
+        loop {
+            // heart-beats are dropped right away
+            self.buffer.advance(leading_eols(&self.buffer));
+
+            if let Some((frame, length)) = StompFrame::decode(&self.buffer)? {
+                self.buffer.advance(length);
+                return Ok(Some(frame));
+            }
+
+            match self.stream.next().await {
+                Some(Ok(Message::Text(text))) => self.buffer.extend_from_slice(text.as_bytes()),
+                Some(Ok(Message::Binary(data))) => self.buffer.extend_from_slice(&data),
+                Some(Ok(Message::Close(_))) | None => return Ok(None),
+                Some(Ok(_)) => {}
+                Some(Err(err)) => return Err(err.into()),
+            }
+        }
+    }
+}
+
+async fn read(mut reader: FrameReader, state: Arc<Mutex<State>>) {
+    while let Ok(Some(frame)) = reader.next().await {
+        let mut state = state.lock().unwrap();
+        state.dispatch(frame);
+
+        if !matches!(state.connection, Connection::Open) {
+            return;
+        }
+    }
+
+    state.lock().unwrap().close(Connection::Closed);
+}
+
+fn error_message(frame: &StompFrame) -> String {
+    frame.header(MESSAGE).unwrap_or(&frame.text).to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spectral::prelude::*;
+    use std::future::Future;
+    use tokio::net::TcpListener;
+
+    struct FakeEdge {
+        ws: WebSocketStream<TcpStream>,
+    }
+
+    impl FakeEdge {
+        async fn receive(&mut self) -> ClientFrame<'static> {
+            let data = self.ws.next().await.unwrap().unwrap().into_data();
+            let (frame, _) = StompClientFrame::decode(&data).unwrap().unwrap();
+            frame.into_owned()
+        }
+
+        async fn send(&mut self, frame: StompFrame) {
+            self.ws.send(Message::from(frame)).await.unwrap();
+        }
+
+        async fn accept(&mut self) -> ConnectClientFrame<'static> {
+            let StompClientFrame::CONNECT(connect_frame) = self.receive().await.frame else {
+                panic!("Expected CONNECT frame");
+            };
+            self.send(StompFrame::connected(
+                "1.2",
+                "fake-edge",
+                "session-1",
+                &HeartBeat::default(),
+            ))
+            .await;
+            connect_frame
+        }
+
+        /// Receives next frame and confirms it with a receipt.
+        async fn confirm(&mut self) -> StompClientFrame<'static> {
+            let ClientFrame { frame, receipt, .. } = self.receive().await;
+            self.send(StompFrame::receipt(&receipt.unwrap())).await;
+            frame
+        }
+    }
+
+    async fn fake_edge<F, Fut>(handler: F) -> String
+    where
+        F: FnOnce(FakeEdge) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            handler(FakeEdge { ws }).await;
+        });
+
+        format!("ws://{address}/ws")
+    }
+
+    #[tokio::test]
+    async fn stomp_client_should_connect_to_host_of_url() {
+        let (sender, connect_frame) = oneshot::channel();
+        let url = fake_edge(|mut edge| async move {
+            let _ = sender.send(edge.accept().await);
+        })
+        .await;
+
+        assert!(StompClient::connect(&url).await.is_ok());
+
+        let connect_frame = connect_frame.await.unwrap();
+        assert_that(&connect_frame.host).is_equal_to(Some("127.0.0.1".into()));
+        assert_that(&connect_frame.accept_version).is_equal_to(vec!["1.2".into()]);
+    }
+
+    #[tokio::test]
+    async fn stomp_client_should_fail_to_connect_when_server_answers_with_error() {
+        let url = fake_edge(|mut edge| async move {
+            edge.receive().await;
+            edge.send(StompFrame::unsupported_version()).await;
+        })
+        .await;
+
+        let result = StompClient::connect(&url).await;
+
+        assert!(matches!(result, Err(StompClientError::Server(_))));
+    }
+
+    #[tokio::test]
+    async fn stomp_client_should_deliver_messages_of_subscription() {
+        let url = fake_edge(|mut edge| async move {
+            edge.accept().await;
+            let StompClientFrame::SUBSCRIBE {
+                destination, id, ..
+            } = edge.confirm().await
+            else {
+                panic!("Expected SUBSCRIBE frame");
+            };
+
+            edge.send(StompFrame::message(
+                &destination,
+                "sub-other",
+                "message-1",
+                "skipped",
+            ))
+            .await;
+            edge.send(StompFrame::message(
+                &destination,
+                &id,
+                "message-2",
+                "Great read!",
+            ))
+            .await;
+        })
+        .await;
+        let client = StompClient::connect(&url).await.unwrap();
+
+        let mut subscription = client.subscribe("article-42").await.unwrap();
+
+        let message = subscription.next().await.unwrap();
+        assert_that(&message.text.as_str()).is_equal_to("Great read!");
+        assert_that(&message.header("destination")).is_equal_to(Some("article-42"));
+        assert_that(&message.header("subscription")).is_equal_to(Some(subscription.id()));
+    }
+
+    #[tokio::test]
+    async fn stomp_client_should_complete_send_once_confirmed() {
+        let (sender, sent_frame) = oneshot::channel();
+        let url = fake_edge(|mut edge| async move {
+            edge.accept().await;
+            let _ = sender.send(edge.confirm().await);
+        })
+        .await;
+        let client = StompClient::connect(&url).await.unwrap();
+
+        assert_that(&client.send_update("comment-1", "edited").await).is_ok();
+
+        assert_that(&sent_frame.await.unwrap()).is_equal_to(StompClientFrame::SEND(
+            SendClientFrame::UPDATE {
+                id: "comment-1".into(),
+                text: "edited".into(),
+                metadata: SendMetadata {
+                    content_type: Some("text/plain;charset=utf-8".into()),
+                    ..SendMetadata::default()
+                },
+            },
+        ));
+    }
+
+    #[tokio::test]
+    async fn stomp_client_should_send_metadata() {
+        let (sender, sent_frame) = oneshot::channel();
+        let url = fake_edge(|mut edge| async move {
+            edge.accept().await;
+            let _ = sender.send(edge.confirm().await);
+        })
+        .await;
+        let client = StompClient::connect(&url).await.unwrap();
+        let frame = SendClientFrame::DELETE {
+            id: "comment-1".into(),
+            metadata: SendMetadata {
+                client_message_id: Some("local-7".into()),
+                custom: vec![("x-locale".into(), "en".into())],
+                ..SendMetadata::default()
+            },
+        };
+
+        assert_that(&client.send(frame.clone()).await).is_ok();
+
+        assert_that(&sent_frame.await.unwrap()).is_equal_to(StompClientFrame::SEND(frame));
+    }
+
+    #[tokio::test]
+    async fn stomp_client_should_fail_request_and_end_subscriptions_on_error() {
+        let url = fake_edge(|mut edge| async move {
+            edge.accept().await;
+            edge.confirm().await;
+            let ClientFrame { receipt, .. } = edge.receive().await;
+            edge.send(StompFrame::receipt_error(
+                "Comment not found",
+                &receipt.unwrap(),
+            ))
+            .await;
+        })
+        .await;
+        let client = StompClient::connect(&url).await.unwrap();
+        let mut subscription = client.subscribe("article-42").await.unwrap();
+
+        let result = client.send_delete("comment-1").await;
+
+        assert!(
+            matches!(result, Err(StompClientError::Server(message)) if message == "Comment not found")
+        );
+        assert_that(&subscription.next().await).is_none();
+        assert!(matches!(
+            client.send_delete("comment-1").await,
+            Err(StompClientError::Server(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn stomp_client_should_forget_receipt_of_request_that_was_not_sent() {
+        let url = fake_edge(|mut edge| async move {
+            edge.accept().await;
+            while edge.ws.next().await.is_some() {}
+        })
+        .await;
+        let client = StompClient::connect(&url).await.unwrap();
+        client.sink.lock().await.close().await.unwrap();
+
+        assert_that(&client.send_delete("comment-1").await).is_err();
+
+        assert_that(&client.state.lock().unwrap().receipts.is_empty()).is_true();
+    }
+
+    #[tokio::test]
+    async fn stomp_client_should_close_connection_after_disconnect_receipt() {
+        let (sender, closed) = oneshot::channel();
+        let url = fake_edge(|mut edge| async move {
+            edge.accept().await;
+            let frame = edge.confirm().await;
+            let close = edge.ws.next().await.unwrap().unwrap();
+            let _ = sender.send((frame, close.is_close()));
+        })
+        .await;
+        let client = StompClient::connect(&url).await.unwrap();
+
+        assert_that(&client.disconnect().await).is_ok();
+
+        assert_that(&closed.await.unwrap()).is_equal_to((StompClientFrame::DISCONNECT, true));
+    }
+}
@@ -0,0 +1,360 @@
+use bytes::{Buf, Bytes, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::error::{StompCodecError, StompParseError};
+use crate::stomp::{
+    leading_eols, ClientFrame, Missing, ParserLimits, StompClientFrame, StompFrame,
+};
+
+/// `tokio_util` codec decoding client frames and encoding server frames, so STOMP can be spoken
+/// over any byte stream (e.g. `Framed<TcpStream, StompCodec>`).
+/// EOLs sent between frames as heart-beats are skipped. Frames (and incomplete data) exceeding
+/// limits are rejected, so a peer can not make the codec buffer arbitrary amount of data.
+#[derive(Default, Clone, Copy, Debug)]
+pub struct StompCodec {
+    limits: ParserLimits,
+    missing: Missing,
+}
+
+impl StompCodec {
+    pub fn new() -> StompCodec {
+        StompCodec::default()
+    }
+
+    pub fn with_limits(limits: ParserLimits) -> StompCodec {
+        StompCodec {
+            limits,
+            ..StompCodec::default()
+        }
+    }
+}
+
+impl Decoder for StompCodec {
+    type Item = ClientFrame<'static>;
+    type Error = StompCodecError;
+
+    fn decode(
+        &mut self,
+        src: &mut BytesMut,
+    ) -> Result<Option<ClientFrame<'static>>, StompCodecError> {
+        // drop heart-beats right away so idle connection does not grow the buffer
+        skip_heart_beats(src, &mut self.missing);
+
+        match StompClientFrame::decode_resumable(src, &self.limits, &mut self.missing)? {
+            Some((frame, length)) => {
+                let frame = frame.into_owned();
+                src.advance(length);
+                Ok(Some(frame))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+impl Encoder<StompFrame> for StompCodec {
+    type Error = StompCodecError;
+
+    fn encode(&mut self, frame: StompFrame, dst: &mut BytesMut) -> Result<(), StompCodecError> {
+        frame.encode(dst);
+        Ok(())
+    }
+}
+
+/// Stateful decoder for transports delivering frames in arbitrary chunks (e.g. websocket messages).
+/// Single chunk can carry any number of frames and a frame can be split across many chunks.
+///
+/// After an error is returned the buffered data is left untouched and the connection should be closed.
+///
+/// # Examples
+/// ```
+/// use commenter_stomp::codec::StompDecoder;
+/// use commenter_stomp::stomp::StompClientFrame;
+///
+/// let mut decoder = StompDecoder::new();
+/// decoder.push(b"UNSUBSCRIBE\nid:sub-1\n\n\0\nDISCO");
+///
+/// assert_eq!(
+///     decoder.next_frame().unwrap().unwrap().frame,
+///     StompClientFrame::UNSUBSCRIBE("sub-1".into())
+/// );
+/// assert_eq!(decoder.next_frame().unwrap(), None);
+///
+/// decoder.push(b"NNECT\nreceipt:77\n\n\0");
+/// let disconnect = decoder.next_frame().unwrap().unwrap();
+/// assert_eq!(disconnect.frame, StompClientFrame::DISCONNECT);
+/// assert_eq!(disconnect.receipt, Some("77".into()));
+/// ```
+#[derive(Default, Debug)]
+pub struct StompDecoder {
+    buffer: BytesMut,
+    last_frame: Bytes,
+    limits: ParserLimits,
+    missing: Missing,
+}
+
+impl StompDecoder {
+    pub fn new() -> StompDecoder {
+        StompDecoder::default()
+    }
+
+    /// Decoder rejecting frames (and incomplete data) that exceed `limits`,
+    /// so a client can not make the server buffer arbitrary amount of data.
+    pub fn with_limits(limits: ParserLimits) -> StompDecoder {
+        StompDecoder {
+            limits,
+            ..StompDecoder::default()
+        }
+    }
+
+    /// Decoder rejecting frames (and incomplete data) longer than `max_frame_length` bytes.
+    pub fn with_max_frame_length(max_frame_length: usize) -> StompDecoder {
+        StompDecoder::with_limits(ParserLimits {
+            max_frame_length,
+            ..ParserLimits::default()
+        })
+    }
+
+    /// Appends received chunk to the internal buffer.
+    pub fn push(&mut self, chunk: &[u8]) {
+        self.buffer.extend_from_slice(chunk);
+    }
+
+    /// Takes next complete frame out of the buffer, `None` if more data is needed.
+    /// Frame is parsed in place and only its header values and body are copied out. Incomplete
+    /// frame is not parsed again until data it is waiting for (e.g. its NUL) is pushed.
+    pub fn next_frame(&mut self) -> Result<Option<ClientFrame<'static>>, StompParseError> {
+        skip_heart_beats(&mut self.buffer, &mut self.missing);
+
+        let Some((frame, length)) =
+            StompClientFrame::decode_resumable(&self.buffer, &self.limits, &mut self.missing)?
+        else {
+            return Ok(None);
+        };
+
+        let frame = frame.into_owned();
+        self.last_frame = self.buffer.split_to(length).freeze();
+        Ok(Some(frame))
+    }
+
+    /// Raw bytes of the frame most recently returned by `next_frame`.
+    pub fn last_frame(&self) -> &[u8] {
+        &self.last_frame
+    }
+
+    /// Raw bytes received but not decoded yet, after an error this starts with the malformed frame.
+    pub fn buffered(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    /// Appends received chunk and returns all frames it completed.
+    pub fn decode_chunk(
+        &mut self,
+        chunk: &[u8],
+    ) -> Result<Vec<ClientFrame<'static>>, StompParseError> {
+        self.push(chunk);
+
+        let mut frames = Vec::new();
+        while let Some(frame) = self.next_frame()? {
+            frames.push(frame);
+        }
+
+        Ok(frames)
+    }
+}
+
+/// Drops EOLs preceding the next frame. What an incomplete frame is waiting for is tracked
+/// by its position in the buffer, so it is forgotten once the start of the buffer moves.
+fn skip_heart_beats(buffer: &mut BytesMut, missing: &mut Missing) {
+    let eols = leading_eols(buffer);
+
+    if eols > 0 {
+        buffer.advance(eols);
+        *missing = Missing::Unknown;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stomp::{AckMode, SendClientFrame, SendMetadata};
+    use spectral::prelude::*;
+
+    const SUBSCRIBE: &[u8] = b"SUBSCRIBE\r\ndestination:topic-1\r\nid:sub-1\r\n\r\n\0";
+    const SEND: &[u8] = b"SEND\naction:CREATE\ndestination:topic-1\ncontent-length:3\n\na\0b\0";
+    const DISCONNECT: &[u8] = b"DISCONNECT\n\n\0";
+
+    #[test]
+    fn stomp_decoder_should_decode_multiple_frames_from_single_chunk() {
+        let mut decoder = StompDecoder::new();
+        let chunk = [SUBSCRIBE, b"\n", SEND, b"\r\n\n", DISCONNECT].concat();
+
+        let frames = decoder.decode_chunk(&chunk);
+
+        assert_that(&frames).is_ok().is_equal_to(vec![
+            subscribe_frame(),
+            send_frame(),
+            StompClientFrame::DISCONNECT.into(),
+        ]);
+    }
+
+    #[test]
+    fn stomp_decoder_should_decode_frames_split_across_chunks() {
+        let mut decoder = StompDecoder::new();
+        let input = [SUBSCRIBE, SEND, DISCONNECT].concat();
+        let mut frames = Vec::new();
+
+        for chunk in input.chunks(3) {
+            frames.extend(decoder.decode_chunk(chunk).unwrap());
+        }
+
+        assert_that(&frames).is_equal_to(vec![
+            subscribe_frame(),
+            send_frame(),
+            StompClientFrame::DISCONNECT.into(),
+        ]);
+    }
+
+    #[test]
+    fn stomp_decoder_should_not_yield_frames_for_heart_beats() {
+        let mut decoder = StompDecoder::new();
+
+        let frames = decoder.decode_chunk(b"\n\r\n\n");
+
+        assert_that(&frames).is_ok().is_empty();
+        assert_that(&decoder.buffer.len()).is_equal_to(0);
+    }
+
+    #[test]
+    fn stomp_decoder_should_fail_on_malformed_frame() {
+        let mut decoder = StompDecoder::new();
+
+        assert_that(&decoder.decode_chunk(b"\nSUBSCRIBE\ndestination\n\n\0"))
+            .is_err_containing(StompParseError::MalformedHeader("destination".to_owned()));
+        assert_that(&decoder.buffered()).is_equal_to(&b"SUBSCRIBE\ndestination\n\n\0"[..]);
+    }
+
+    #[test]
+    fn stomp_decoder_should_reject_frame_exceeding_max_length() {
+        let mut decoder = StompDecoder::with_max_frame_length(SUBSCRIBE.len());
+
+        assert_that(&decoder.decode_chunk(&[SUBSCRIBE, &SEND[..50]].concat()))
+            .is_err_containing(StompParseError::FrameTooLarge(SUBSCRIBE.len()));
+        assert_that(&decoder.last_frame()).is_equal_to(SUBSCRIBE);
+    }
+
+    #[test]
+    fn stomp_decoder_should_reject_incomplete_frame_exceeding_limits() {
+        let limits = ParserLimits {
+            max_header_line_length: 24,
+            ..ParserLimits::default()
+        };
+        let mut decoder = StompDecoder::with_limits(limits);
+
+        assert_that(&decoder.decode_chunk(b"SEND\ndestination:topic-1\naction")).is_ok();
+        assert_that(&decoder.decode_chunk(b":CREATE\ncontent-type:text/plain;charset=utf-8"))
+            .is_err_containing(StompParseError::HeaderLineTooLong(24));
+    }
+
+    #[test]
+    fn stomp_decoder_should_wait_for_announced_content_length() {
+        let mut decoder = StompDecoder::new();
+
+        assert_that(&decoder.decode_chunk(&SEND[..SEND.len() - 3]))
+            .is_ok()
+            .is_empty();
+        assert_that(&decoder.missing).is_equal_to(Missing::Length(SEND.len()));
+        assert_that(&decoder.decode_chunk(&SEND[SEND.len() - 3..]))
+            .is_ok()
+            .is_equal_to(vec![send_frame()]);
+    }
+
+    #[test]
+    fn stomp_decoder_should_resume_search_for_nul_where_it_stopped() {
+        let mut decoder = StompDecoder::new();
+
+        assert_that(&decoder.decode_chunk(&SUBSCRIBE[..SUBSCRIBE.len() - 1])).is_ok();
+        decoder.push(b"\r\n");
+        assert_that(&decoder.next_frame()).is_ok().is_none();
+        assert_that(&decoder.missing).is_equal_to(Missing::Nul {
+            start: SUBSCRIBE.len() - 1,
+            scanned: SUBSCRIBE.len() + 1,
+        });
+    }
+
+    #[test]
+    fn stomp_decoder_should_forget_what_frame_waits_for_once_heart_beat_is_dropped() {
+        let mut decoder = StompDecoder::new();
+
+        assert_that(&decoder.decode_chunk(b"\r")).is_ok().is_empty();
+        assert_that(&decoder.decode_chunk(&[b"\n", DISCONNECT].concat()))
+            .is_ok()
+            .is_equal_to(vec![StompClientFrame::DISCONNECT.into()]);
+    }
+
+    #[test]
+    fn stomp_decoder_should_keep_last_decoded_frame() {
+        let mut decoder = StompDecoder::new();
+        decoder.push(&[b"\n", SUBSCRIBE, b"\n", DISCONNECT].concat());
+
+        assert_that(&decoder.next_frame()).is_ok().is_some();
+        assert_that(&decoder.last_frame()).is_equal_to(SUBSCRIBE);
+        assert_that(&decoder.next_frame()).is_ok().is_some();
+        assert_that(&decoder.last_frame()).is_equal_to(DISCONNECT);
+    }
+
+    #[test]
+    fn stomp_codec_should_leave_incomplete_frame_in_buffer() {
+        let mut codec = StompCodec::new();
+        let mut buffer = BytesMut::from(&[DISCONNECT, &SUBSCRIBE[..10]].concat()[..]);
+
+        assert_that(&codec.decode(&mut buffer))
+            .is_ok()
+            .is_some()
+            .is_equal_to(ClientFrame::from(StompClientFrame::DISCONNECT));
+        assert_that(&codec.decode(&mut buffer)).is_ok().is_none();
+        assert_that(&&buffer[..]).is_equal_to(&SUBSCRIBE[..10]);
+    }
+
+    #[test]
+    fn stomp_codec_should_reject_frame_exceeding_limits() {
+        let mut codec = StompCodec::with_limits(ParserLimits {
+            max_body_length: 2,
+            ..ParserLimits::default()
+        });
+        let mut buffer = BytesMut::from(SEND);
+
+        assert!(matches!(
+            codec.decode(&mut buffer),
+            Err(StompCodecError::Parse(StompParseError::BodyTooLarge(2)))
+        ));
+    }
+
+    #[test]
+    fn stomp_codec_should_encode_nul_terminated_frame() {
+        let mut buffer = BytesMut::new();
+
+        StompCodec::new()
+            .encode(StompFrame::error("failure"), &mut buffer)
+            .unwrap();
+
+        assert_that(&&buffer[..]).is_equal_to(&b"ERROR\nmessage:failure\n\n\0"[..]);
+    }
+
+    fn subscribe_frame() -> ClientFrame<'static> {
+        StompClientFrame::SUBSCRIBE {
+            destination: "topic-1".into(),
+            id: "sub-1".into(),
+            ack: AckMode::Auto,
+        }
+        .into()
+    }
+
+    fn send_frame() -> ClientFrame<'static> {
+        StompClientFrame::SEND(SendClientFrame::CREATE {
+            destination: "topic-1".into(),
+            text: "a\0b".into(),
+            metadata: SendMetadata::default(),
+        })
+        .into()
+    }
+}
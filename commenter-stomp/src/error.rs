@@ -0,0 +1,94 @@
+use std::io;
+use std::str::Utf8Error;
+
+use thiserror::Error;
+
+/// Reasons for rejecting a client frame. Every variant is a fatal protocol error,
+/// server is expected to answer with ERROR frame and close the connection.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum StompParseError {
+    #[error("Frame does not start with a command")]
+    MissingCommand,
+
+    #[error("Unrecognized command {0}")]
+    UnknownCommand(String),
+
+    #[error("{command} frame requires {header} header")]
+    MissingHeader {
+        header: &'static str,
+        command: &'static str,
+    },
+
+    #[error("Unrecognized action type {0}")]
+    UnknownAction(String),
+
+    #[error("Unable to parse header line {0}")]
+    MalformedHeader(String),
+
+    #[error("Undefined or unterminated escape sequence in header {0}")]
+    InvalidEscapeSequence(String),
+
+    #[error("Invalid {header} header {value}")]
+    InvalidHeaderValue { header: &'static str, value: String },
+
+    #[error("Frame body does not match content-length {0}")]
+    ContentLengthMismatch(usize),
+
+    #[error("Frame is not valid UTF-8")]
+    InvalidUtf8(#[from] Utf8Error),
+
+    #[error("Frame exceeds maximum length of {0} bytes")]
+    FrameTooLarge(usize),
+
+    #[error("Frame exceeds maximum of {0} headers")]
+    TooManyHeaders(usize),
+
+    #[error("Header line exceeds maximum length of {0} bytes")]
+    HeaderLineTooLong(usize),
+
+    #[error("Frame body exceeds maximum length of {0} bytes")]
+    BodyTooLarge(usize),
+
+    #[error("Unexpected end of frame")]
+    IncompleteFrame,
+
+    #[error("Unexpected data after frame NUL terminator")]
+    TrailingData,
+}
+
+/// Error of [`StompCodec`](crate::codec::StompCodec), underlying transport can fail as well.
+#[derive(Error, Debug)]
+pub enum StompCodecError {
+    #[error(transparent)]
+    Parse(#[from] StompParseError),
+
+    #[error("Error on transport interaction")]
+    Io(#[from] io::Error),
+}
+
+/// Failure of a [`StompClient`](crate::client::StompClient) request.
+#[cfg(feature = "client")]
+#[derive(Error, Debug)]
+pub enum StompClientError {
+    #[error("Error on websocket interaction")]
+    Websocket(#[source] Box<tungstenite::Error>),
+
+    #[error(transparent)]
+    Parse(#[from] StompParseError),
+
+    #[error("Server sent ERROR frame: {0}")]
+    Server(String),
+
+    #[error("Unexpected {0} frame")]
+    UnexpectedFrame(String),
+
+    #[error("Connection is closed")]
+    Closed,
+}
+
+#[cfg(feature = "client")]
+impl From<tungstenite::Error> for StompClientError {
+    fn from(err: tungstenite::Error) -> StompClientError {
+        StompClientError::Websocket(Box::new(err))
+    }
+}
@@ -1 +1,10 @@
-pub mod stomp;
\ No newline at end of file
+#[cfg(feature = "client")]
+pub mod client;
+pub mod codec;
+pub mod error;
+pub mod stomp;
+
+#[cfg(feature = "tungstenite")]
+mod tungstenite_ws;
+#[cfg(feature = "warp")]
+mod warp_ws;
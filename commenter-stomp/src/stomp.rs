@@ -1,332 +1,3055 @@
-use anyhow::{bail, Ok, Result};
-use std::collections::HashMap;
+use bytes::BufMut;
+use std::borrow::Cow;
+use std::convert::Infallible;
+use std::fmt;
+use std::io;
 use std::str;
-use warp::ws::Message;
+use std::sync::Arc;
+use std::time::Duration;
 
-// use crate::comments::{Comment, CommentState};
+use crate::error::StompParseError;
+
+type Result<T> = std::result::Result<T, StompParseError>;
+
+pub const SUPPORTED_VERSIONS: [&str; 1] = ["1.2"];
 
 const DESTINATION: &str = "destination";
 const ACTION: &str = "action";
 const ID: &str = "id";
+const ACK: &str = "ack";
+const ACCEPT_VERSION: &str = "accept-version";
+const HOST: &str = "host";
+const LOGIN: &str = "login";
+const PASSCODE: &str = "passcode";
+const HEART_BEAT: &str = "heart-beat";
+const VERSION: &str = "version";
+const SERVER: &str = "server";
+const SESSION: &str = "session";
+pub(crate) const MESSAGE: &str = "message";
+pub(crate) const SUBSCRIPTION: &str = "subscription";
+const MESSAGE_ID: &str = "message-id";
+const CONTENT_LENGTH: &str = "content-length";
+const CONTENT_TYPE: &str = "content-type";
+const RECEIPT: &str = "receipt";
+pub(crate) const RECEIPT_ID: &str = "receipt-id";
+const TRANSACTION: &str = "transaction";
+const CLIENT_MESSAGE_ID: &str = "client-message-id";
+const REPLY_TO: &str = "reply-to";
+const CUSTOM_HEADER_PREFIX: &str = "x-";
+
+const DEFAULT_CONTENT_TYPE: &str = "text/plain;charset=utf-8";
+const MAX_ERROR_EXCERPT_LENGTH: usize = 256;
+
+/// Frame sent by the server. Headers keep the order they were added in, a repeated header is
+/// allowed and receivers only take its first occurrence into account.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StompFrame {
+    pub command: String,
+    pub headers: Vec<(String, String)>,
+    pub text: String,
+}
+
+/// Frame sent by a client. Parsed frames borrow header values and body from the received data,
+/// `into_owned` copies them when the frame has to outlive the buffer.
+#[derive(PartialEq, Debug, Clone)]
+pub enum StompClientFrame<'a> {
+    CONNECT(ConnectClientFrame<'a>),
+    STOMP(ConnectClientFrame<'a>),
+    SEND(SendClientFrame<'a>),
+    SUBSCRIBE {
+        destination: Cow<'a, str>,
+        id: Cow<'a, str>,
+        ack: AckMode,
+    },
+    UNSUBSCRIBE(Cow<'a, str>),
+    ACK(Cow<'a, str>),
+    NACK(Cow<'a, str>),
+    BEGIN(Cow<'a, str>),
+    COMMIT(Cow<'a, str>),
+    ABORT(Cow<'a, str>),
+    DISCONNECT,
+}
+
+/// How a client acknowledges messages of a subscription, requested by `ack` header of SUBSCRIBE frame.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum AckMode {
+    /// Messages are considered consumed as soon as they are sent.
+    #[default]
+    Auto,
+    /// ACK confirms given message together with all messages sent to the subscription before it.
+    Client,
+    /// ACK confirms only the given message.
+    ClientIndividual,
+}
+
+/// Client frame together with the `receipt` header, when client asked to confirm its processing,
+/// and the `transaction` header of SEND, ACK and NACK frames that are part of a transaction.
+#[derive(PartialEq, Debug, Clone)]
+pub struct ClientFrame<'a> {
+    pub frame: StompClientFrame<'a>,
+    pub receipt: Option<Cow<'a, str>>,
+    pub transaction: Option<Cow<'a, str>>,
+}
+
+impl<'a> From<StompClientFrame<'a>> for ClientFrame<'a> {
+    fn from(frame: StompClientFrame<'a>) -> ClientFrame<'a> {
+        ClientFrame {
+            frame,
+            receipt: None,
+            transaction: None,
+        }
+    }
+}
+
+impl ClientFrame<'_> {
+    pub fn into_owned(self) -> ClientFrame<'static> {
+        ClientFrame {
+            frame: self.frame.into_owned(),
+            receipt: self.receipt.map(owned),
+            transaction: self.transaction.map(owned),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum SendClientFrame<'a> {
+    CREATE {
+        destination: Cow<'a, str>,
+        text: Cow<'a, str>,
+        metadata: SendMetadata<'a>,
+    },
+    UPDATE {
+        id: Cow<'a, str>,
+        text: Cow<'a, str>,
+        metadata: SendMetadata<'a>,
+    },
+    DELETE {
+        id: Cow<'a, str>,
+        metadata: SendMetadata<'a>,
+    },
+}
+
+impl<'a> SendClientFrame<'a> {
+    pub fn metadata(&self) -> &SendMetadata<'a> {
+        match self {
+            SendClientFrame::CREATE { metadata, .. }
+            | SendClientFrame::UPDATE { metadata, .. }
+            | SendClientFrame::DELETE { metadata, .. } => metadata,
+        }
+    }
+
+    pub fn into_owned(self) -> SendClientFrame<'static> {
+        match self {
+            SendClientFrame::CREATE {
+                destination,
+                text,
+                metadata,
+            } => SendClientFrame::CREATE {
+                destination: owned(destination),
+                text: owned(text),
+                metadata: metadata.into_owned(),
+            },
+            SendClientFrame::UPDATE { id, text, metadata } => SendClientFrame::UPDATE {
+                id: owned(id),
+                text: owned(text),
+                metadata: metadata.into_owned(),
+            },
+            SendClientFrame::DELETE { id, metadata } => SendClientFrame::DELETE {
+                id: owned(id),
+                metadata: metadata.into_owned(),
+            },
+        }
+    }
+}
+
+/// Optional headers of a SEND frame that are passed through to subscribers, on the MESSAGE
+/// frames delivering the comment. Lets a client correlate its own comment when it is echoed back.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct SendMetadata<'a> {
+    /// `content-type` of the body.
+    pub content_type: Option<Cow<'a, str>>,
+    /// `client-message-id` the client identifies the comment by before it gets its id.
+    pub client_message_id: Option<Cow<'a, str>>,
+    /// `reply-to` header.
+    pub reply_to: Option<Cow<'a, str>>,
+    /// User-defined headers prefixed with `x-`, in the order they were received.
+    pub custom: Vec<(Cow<'a, str>, Cow<'a, str>)>,
+}
+
+impl<'a> SendMetadata<'a> {
+    /// Picks metadata out of `headers`, other headers are ignored. When a header is repeated,
+    /// only its first occurrence counts.
+    ///
+    /// # Examples
+    /// ```
+    /// use commenter_stomp::stomp::SendMetadata;
+    ///
+    /// let metadata = SendMetadata::from_headers([
+    ///     ("destination".into(), "article-42".into()),
+    ///     ("client-message-id".into(), "local-7".into()),
+    ///     ("x-locale".into(), "en".into()),
+    /// ]);
+    ///
+    /// assert_eq!(metadata.client_message_id.as_deref(), Some("local-7"));
+    /// assert_eq!(
+    ///     metadata.headers().collect::<Vec<_>>(),
+    ///     vec![("client-message-id", "local-7"), ("x-locale", "en")]
+    /// );
+    /// ```
+    pub fn from_headers<I>(headers: I) -> SendMetadata<'a>
+    where
+        I: IntoIterator<Item = (Cow<'a, str>, Cow<'a, str>)>,
+    {
+        let mut metadata = SendMetadata::default();
+
+        for (key, value) in headers {
+            let slot = match key.as_ref() {
+                CONTENT_TYPE => &mut metadata.content_type,
+                CLIENT_MESSAGE_ID => &mut metadata.client_message_id,
+                REPLY_TO => &mut metadata.reply_to,
+                custom if custom.starts_with(CUSTOM_HEADER_PREFIX) => {
+                    if !metadata.custom.iter().any(|(name, _)| *name == key) {
+                        metadata.custom.push((key, value));
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+
+            slot.get_or_insert(value);
+        }
+
+        metadata
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.headers().next().is_none()
+    }
+
+    /// Headers carrying the metadata, in the order they are written to frames.
+    pub fn headers(&self) -> impl Iterator<Item = (&str, &str)> {
+        [
+            (CONTENT_TYPE, &self.content_type),
+            (CLIENT_MESSAGE_ID, &self.client_message_id),
+            (REPLY_TO, &self.reply_to),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| Some((key, value.as_deref()?)))
+        .chain(
+            self.custom
+                .iter()
+                .map(|(key, value)| (key.as_ref(), value.as_ref())),
+        )
+    }
+
+    pub fn into_owned(self) -> SendMetadata<'static> {
+        SendMetadata {
+            content_type: self.content_type.map(owned),
+            client_message_id: self.client_message_id.map(owned),
+            reply_to: self.reply_to.map(owned),
+            custom: self
+                .custom
+                .into_iter()
+                .map(|(key, value)| (owned(key), owned(value)))
+                .collect(),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct ConnectClientFrame<'a> {
+    pub accept_version: Vec<Cow<'a, str>>,
+    pub host: Option<Cow<'a, str>>,
+    pub login: Option<Cow<'a, str>>,
+    pub passcode: Option<Cow<'a, str>>,
+    pub heart_beat: HeartBeat,
+}
+
+/// Upper bounds enforced while parsing client frames, so a client can not make the server buffer
+/// arbitrary amount of data. Lengths are in bytes, header line length does not include its EOL.
+/// Default limits are unbounded.
+///
+/// # Examples
+/// ```
+/// use commenter_stomp::error::StompParseError;
+/// use commenter_stomp::stomp::{ParserLimits, StompClientFrame};
+///
+/// let limits = ParserLimits {
+///     max_headers: 1,
+///     ..ParserLimits::default()
+/// };
+///
+/// let result = StompClientFrame::parse_with_limits(b"SUBSCRIBE\nid:1\ndestination:a\n\n\0", &limits);
+/// assert_eq!(result, Err(StompParseError::TooManyHeaders(1)));
+/// ```
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct ParserLimits {
+    pub max_frame_length: usize,
+    pub max_headers: usize,
+    pub max_header_line_length: usize,
+    pub max_body_length: usize,
+}
+
+impl Default for ParserLimits {
+    fn default() -> ParserLimits {
+        ParserLimits {
+            max_frame_length: usize::MAX,
+            max_headers: usize::MAX,
+            max_header_line_length: usize::MAX,
+            max_body_length: usize::MAX,
+        }
+    }
+}
+
+/// What an incomplete frame is waiting for, so decoders fed in chunks do not parse it again
+/// until something that can complete it (or make it exceed limits) arrives.
+/// Positions are relative to the start of the data the frame was found incomplete in.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub(crate) enum Missing {
+    /// Nothing is known, data is parsed again whenever more of it arrives.
+    #[default]
+    Unknown,
+    /// EOL of the line starting at `start`, it is not among bytes before `scanned`.
+    Eol { start: usize, scanned: usize },
+    /// NUL of the body starting at `start`, it is not among bytes before `scanned`.
+    Nul { start: usize, scanned: usize },
+    /// Frame ends once there are this many bytes, as announced by `content-length`.
+    Length(usize),
+}
+
+impl Missing {
+    /// Whether `raw`, that is the data the frame was found incomplete in with more appended to it,
+    /// is worth parsing again. Only the appended bytes are scanned.
+    fn may_have_arrived(&mut self, raw: &[u8], limits: &ParserLimits) -> bool {
+        if raw.len() > limits.max_frame_length {
+            return true;
+        }
+
+        match *self {
+            Missing::Unknown => true,
+            Missing::Eol { start, scanned } => {
+                // partial line may still end with \r that is not counted in its length
+                if raw.len() - start > limits.max_header_line_length.saturating_add(1)
+                    || raw[scanned..].contains(&b'\n')
+                {
+                    return true;
+                }
+
+                *self = Missing::Eol {
+                    start,
+                    scanned: raw.len(),
+                };
+                false
+            }
+            Missing::Nul { start, scanned } => {
+                if raw.len() - start > limits.max_body_length || raw[scanned..].contains(&0) {
+                    return true;
+                }
+
+                *self = Missing::Nul {
+                    start,
+                    scanned: raw.len(),
+                };
+                false
+            }
+            Missing::Length(length) => raw.len() >= length,
+        }
+    }
+}
+
+/// Heart-beat intervals in milliseconds, as announced by `heart-beat:cx,cy` header.
+/// Zero means that given side is not able to send or does not want to receive heart-beats.
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub struct HeartBeat {
+    pub outgoing: u32,
+    pub incoming: u32,
+}
+
+/// Heart-beating agreed between both sides of the connection, `None` means no heart-beats.
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub struct NegotiatedHeartBeat {
+    pub outgoing: Option<Duration>,
+    pub incoming: Option<Duration>,
+}
+
+impl HeartBeat {
+    /// Negotiates heart-beating from the point of view of the side that announced `self`,
+    /// `other` being heart-beat announced by the other side of the connection.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::time::Duration;
+    /// use commenter_stomp::stomp::HeartBeat;
+    ///
+    /// let server = HeartBeat { outgoing: 10000, incoming: 10000 };
+    /// let client = HeartBeat { outgoing: 0, incoming: 20000 };
+    ///
+    /// let negotiated = server.negotiate(&client);
+    /// assert_eq!(negotiated.outgoing, Some(Duration::from_millis(20000)));
+    /// assert_eq!(negotiated.incoming, None);
+    /// ```
+    pub fn negotiate(&self, other: &HeartBeat) -> NegotiatedHeartBeat {
+        NegotiatedHeartBeat {
+            outgoing: HeartBeat::interval(self.outgoing, other.incoming),
+            incoming: HeartBeat::interval(self.incoming, other.outgoing),
+        }
+    }
+
+    fn interval(sender: u32, receiver: u32) -> Option<Duration> {
+        if sender == 0 || receiver == 0 {
+            None
+        } else {
+            Some(Duration::from_millis(sender.max(receiver).into()))
+        }
+    }
+}
+
+impl fmt::Display for HeartBeat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{}", self.outgoing, self.incoming)
+    }
+}
+
+impl ConnectClientFrame<'_> {
+    /// Picks highest protocol version supported by both client and server.
+    /// Clients that do not send `accept-version` header are assumed to speak STOMP 1.0.
+    ///
+    /// # Examples
+    /// ```
+    /// use commenter_stomp::stomp::{ConnectClientFrame, HeartBeat};
+    ///
+    /// let frame = ConnectClientFrame {
+    ///     accept_version: vec!["1.0".into(), "1.1".into(), "1.2".into()],
+    ///     host: None,
+    ///     login: None,
+    ///     passcode: None,
+    ///     heart_beat: HeartBeat::default(),
+    /// };
+    /// assert_eq!(frame.negotiate_version(), Some("1.2"));
+    /// ```
+    pub fn negotiate_version(&self) -> Option<&'static str> {
+        SUPPORTED_VERSIONS
+            .iter()
+            .rev()
+            .find(|supported| self.accept_version.iter().any(|v| v == *supported))
+            .copied()
+    }
+
+    pub fn into_owned(self) -> ConnectClientFrame<'static> {
+        ConnectClientFrame {
+            accept_version: self.accept_version.into_iter().map(owned).collect(),
+            host: self.host.map(owned),
+            login: self.login.map(owned),
+            passcode: self.passcode.map(owned),
+            heart_beat: self.heart_beat,
+        }
+    }
+}
+
+impl StompFrame {
+    pub fn connected(
+        version: &str,
+        server: &str,
+        session: &str,
+        heart_beat: &HeartBeat,
+    ) -> StompFrame {
+        StompFrame {
+            command: "CONNECTED".to_owned(),
+            headers: vec![
+                (VERSION.to_owned(), version.to_owned()),
+                (SERVER.to_owned(), server.to_owned()),
+                (SESSION.to_owned(), session.to_owned()),
+                (HEART_BEAT.to_owned(), heart_beat.to_string()),
+            ],
+            text: String::new(),
+        }
+    }
+
+    /// MESSAGE frame delivering `text` to the client's subscription `subscription` of `destination`.
+    /// `message_id` identifies the message, so client can refer to it when acknowledging.
+    ///
+    /// # Examples
+    /// ```
+    /// use commenter_stomp::stomp::StompFrame;
+    ///
+    /// let frame = StompFrame::message("article-42", "sub-1", "message-1", "Great read!")
+    ///     .with_header("action", "CREATED");
+    ///
+    /// assert_eq!(frame.header("subscription"), Some("sub-1"));
+    /// assert_eq!(frame.header("action"), Some("CREATED"));
+    /// ```
+    pub fn message(
+        destination: &str,
+        subscription: &str,
+        message_id: &str,
+        text: &str,
+    ) -> StompFrame {
+        let mut frame = StompFrame::broadcast_message(destination, message_id, text);
+        frame
+            .headers
+            .insert(1, (SUBSCRIPTION.to_owned(), subscription.to_owned()));
+        frame
+    }
+
+    /// MESSAGE frame delivering `text` to all subscriptions of `destination`, without `subscription`
+    /// header, which is added to each delivery of [`BroadcastFrame`] built out of it.
+    pub fn broadcast_message(destination: &str, message_id: &str, text: &str) -> StompFrame {
+        StompFrame {
+            command: "MESSAGE".to_owned(),
+            headers: vec![
+                (DESTINATION.to_owned(), destination.to_owned()),
+                (MESSAGE_ID.to_owned(), message_id.to_owned()),
+                (CONTENT_TYPE.to_owned(), DEFAULT_CONTENT_TYPE.to_owned()),
+            ],
+            text: text.to_owned(),
+        }
+    }
+
+    pub fn error(message: &str) -> StompFrame {
+        StompFrame {
+            command: "ERROR".to_owned(),
+            headers: vec![(MESSAGE.to_owned(), message.to_owned())],
+            text: String::new(),
+        }
+    }
+
+    /// ERROR frame sent when client and server do not share any protocol version.
+    pub fn unsupported_version() -> StompFrame {
+        let versions = SUPPORTED_VERSIONS.join(",");
+        StompFrame::error(&format!("Supported protocol versions are {versions}"))
+            .with_header(VERSION, &versions)
+    }
+
+    pub fn receipt(receipt_id: &str) -> StompFrame {
+        StompFrame {
+            command: "RECEIPT".to_owned(),
+            headers: vec![(RECEIPT_ID.to_owned(), receipt_id.to_owned())],
+            text: String::new(),
+        }
+    }
+
+    /// ERROR frame reporting failure of a frame that carried `receipt` header.
+    pub fn receipt_error(message: &str, receipt_id: &str) -> StompFrame {
+        StompFrame::error(message).with_receipt_id(receipt_id)
+    }
+
+    /// ERROR frame with beginning of the offending frame quoted in the body,
+    /// so client can tell which of its frames was rejected.
+    ///
+    /// # Examples
+    /// ```
+    /// use commenter_stomp::stomp::StompFrame;
+    ///
+    /// let frame = StompFrame::error_with_excerpt("Unrecogized command", b"PUBLISH\n\n\0");
+    /// assert_eq!(frame.text, "The message:\n-----\nPUBLISH\n\n\\0\n-----\n");
+    /// ```
+    pub fn error_with_excerpt(message: &str, excerpt: &[u8]) -> StompFrame {
+        let excerpt = &excerpt[..excerpt.len().min(MAX_ERROR_EXCERPT_LENGTH)];
+        let excerpt = String::from_utf8_lossy(excerpt).replace('\0', "\\0");
+
+        let mut frame = StompFrame::error(message);
+        frame.text = format!("The message:\n-----\n{excerpt}\n-----\n");
+        frame
+    }
+
+    pub fn with_receipt_id(self, receipt_id: &str) -> StompFrame {
+        self.with_header(RECEIPT_ID, receipt_id)
+    }
+
+    /// Adds `ack` header to MESSAGE frame sent to a subscription that requires acknowledgement,
+    /// client refers to the message by this value in its ACK or NACK frame.
+    pub fn with_ack(self, ack: &str) -> StompFrame {
+        self.with_header(ACK, ack)
+    }
+
+    /// Adds headers of `metadata` to the frame, its `content-type` replaces the one already set.
+    ///
+    /// # Examples
+    /// ```
+    /// use commenter_stomp::stomp::{SendMetadata, StompFrame};
+    ///
+    /// let metadata = SendMetadata {
+    ///     content_type: Some("application/json".into()),
+    ///     client_message_id: Some("local-7".into()),
+    ///     ..SendMetadata::default()
+    /// };
+    /// let frame = StompFrame::message("article-42", "sub-1", "message-1", "{}")
+    ///     .with_metadata(&metadata);
+    ///
+    /// assert_eq!(frame.header("content-type"), Some("application/json"));
+    /// assert_eq!(frame.header("client-message-id"), Some("local-7"));
+    /// ```
+    pub fn with_metadata(mut self, metadata: &SendMetadata<'_>) -> StompFrame {
+        for (name, value) in metadata.headers() {
+            match self.headers.iter_mut().find(|(key, _)| key == CONTENT_TYPE) {
+                Some((_, content_type)) if name == CONTENT_TYPE => *content_type = value.to_owned(),
+                _ => self.headers.push((name.to_owned(), value.to_owned())),
+            }
+        }
+        self
+    }
+
+    /// Appends header. When the frame already has a header of the same name, receivers will
+    /// only take the first one into account.
+    ///
+    /// # Examples
+    /// ```
+    /// use commenter_stomp::stomp::StompFrame;
+    ///
+    /// let frame = StompFrame::receipt("77").with_header("receipt-id", "78");
+    /// assert_eq!(frame.header("receipt-id"), Some("77"));
+    /// ```
+    pub fn with_header(mut self, name: &str, value: &str) -> StompFrame {
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Value of the first header called `name`.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Serializes the frame, including NUL terminator, into `dst`. Headers are written in
+    /// insertion order, so the same frame is always serialized to the same bytes.
+    /// `content-length` (and `content-type` unless set) is added to frames with a body and to
+    /// MESSAGE frames, any `content-length` set by hand is replaced.
+    ///
+    /// `dst` is only appended to, so a single buffer can be cleared and reused for many frames.
+    ///
+    /// # Examples
+    /// ```
+    /// use bytes::BytesMut;
+    /// use commenter_stomp::stomp::StompFrame;
+    ///
+    /// let mut buffer = BytesMut::new();
+    /// StompFrame::receipt("77").encode(&mut buffer);
+    /// assert_eq!(&buffer[..], b"RECEIPT\nreceipt-id:77\n\n\0");
+    /// ```
+    pub fn encode<B: BufMut>(&self, dst: &mut B) {
+        infallible(self.serialize(&mut BufSink(dst)));
+    }
+
+    /// Serializes the frame, including NUL terminator, into `writer`. Frame is written piece
+    /// by piece, so `writer` should be buffered.
+    pub fn write_to<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.serialize(&mut WriteSink(writer))
+    }
+
+    /// Serializes the frame into a string sized up front for the frame as it is, so it is
+    /// reallocated only when escaping grows the headers.
+    fn to_encoded_string(&self) -> String {
+        // every header line takes a colon and EOL, content headers and NUL take a few more bytes
+        let headers_length: usize = self
+            .headers
+            .iter()
+            .map(|(key, value)| key.len() + value.len() + 2)
+            .sum();
+        let length = self.command.len() + headers_length + self.text.len() + 64;
+
+        let mut data = String::with_capacity(length);
+        infallible(self.serialize(&mut data));
+        data
+    }
+
+    fn serialize<S: Sink>(&self, dst: &mut S) -> std::result::Result<(), S::Error> {
+        let escaped = is_escaped(&self.command);
+        let content_headers = self.command == "MESSAGE" || !self.text.is_empty();
+
+        dst.put(&self.command)?;
+        dst.put("\n")?;
+
+        for (key, value) in self.headers.iter() {
+            if content_headers && key == CONTENT_LENGTH {
+                continue;
+            }
+
+            put_header(dst, key, value, escaped)?;
+        }
+
+        if content_headers {
+            put_header(dst, CONTENT_LENGTH, &self.text.len().to_string(), escaped)?;
+
+            if self.header(CONTENT_TYPE).is_none() {
+                put_header(dst, CONTENT_TYPE, DEFAULT_CONTENT_TYPE, escaped)?;
+            }
+        }
+
+        dst.put("\n")?;
+        dst.put(&self.text)?;
+        dst.put("\0")
+    }
+
+    /// Decodes first frame from the beginning of `raw`, skipping EOLs (heart-beats) preceding it.
+    /// Counterpart of `encode` for the client side, any command is accepted and all headers are kept.
+    /// Returns `None` when `raw` does not contain a complete frame yet, otherwise the frame together
+    /// with number of bytes it occupied.
+    ///
+    /// # Examples
+    /// ```
+    /// use commenter_stomp::stomp::StompFrame;
+    ///
+    /// let (frame, length) = StompFrame::decode(b"\nRECEIPT\nreceipt-id:77\n\n\0").unwrap().unwrap();
+    /// assert_eq!(frame, StompFrame::receipt("77"));
+    /// assert_eq!(length, 25);
+    /// ```
+    pub fn decode(raw: &[u8]) -> Result<Option<(StompFrame, usize)>> {
+        let mut position = leading_eols(raw);
+
+        let Some(command) = read_line(raw, &mut position) else {
+            return Ok(None);
+        };
+
+        let command = str::from_utf8(command)?;
+
+        let Some(header_block) = read_header_block(raw, &mut position, &ParserLimits::default())?
+        else {
+            return Ok(None);
+        };
+
+        let headers = Headers::new(str::from_utf8(header_block)?, is_escaped(command))?;
+
+        let Some(body) = read_body(
+            raw,
+            position,
+            headers.get(CONTENT_LENGTH).as_deref(),
+            usize::MAX,
+        )?
+        else {
+            return Ok(None);
+        };
+
+        let frame = StompFrame {
+            command: command.to_owned(),
+            headers: headers
+                .iter()
+                .map(|(key, value)| (key.into_owned(), value.into_owned()))
+                .collect(),
+            text: str::from_utf8(body)?.to_owned(),
+        };
+
+        Ok(Some((frame, position + body.len() + 1)))
+    }
+}
+
+/// Builds the frame a client sends, so client frames can be serialized with [`StompFrame::encode`].
+///
+/// # Examples
+/// ```
+/// use commenter_stomp::stomp::{ClientFrame, StompClientFrame, StompFrame};
+///
+/// let frame = StompFrame::from(ClientFrame {
+///     frame: StompClientFrame::UNSUBSCRIBE("sub-1".into()),
+///     receipt: Some("77".into()),
+///     transaction: None,
+/// });
+/// assert_eq!(String::from(frame), "UNSUBSCRIBE\nid:sub-1\nreceipt:77\n\n\0");
+/// ```
+impl From<ClientFrame<'_>> for StompFrame {
+    fn from(client_frame: ClientFrame<'_>) -> StompFrame {
+        let mut metadata = SendMetadata::default();
+        let (command, mut headers, text) = match client_frame.frame {
+            StompClientFrame::CONNECT(frame) => ("CONNECT", connect_headers(frame), String::new()),
+            StompClientFrame::STOMP(frame) => ("STOMP", connect_headers(frame), String::new()),
+            StompClientFrame::SEND(frame) => {
+                let (headers, text) = match frame {
+                    SendClientFrame::CREATE {
+                        destination,
+                        text,
+                        metadata: send_metadata,
+                    } => {
+                        metadata = send_metadata;
+                        (
+                            vec![(ACTION, "CREATE".into()), (DESTINATION, destination)],
+                            text.into_owned(),
+                        )
+                    }
+                    SendClientFrame::UPDATE {
+                        id,
+                        text,
+                        metadata: send_metadata,
+                    } => {
+                        metadata = send_metadata;
+                        (vec![(ACTION, "UPDATE".into()), (ID, id)], text.into_owned())
+                    }
+                    SendClientFrame::DELETE {
+                        id,
+                        metadata: send_metadata,
+                    } => {
+                        metadata = send_metadata;
+                        (vec![(ACTION, "DELETE".into()), (ID, id)], String::new())
+                    }
+                };
+                ("SEND", headers, text)
+            }
+            StompClientFrame::SUBSCRIBE {
+                destination,
+                id,
+                ack,
+            } => {
+                let mut headers = vec![(DESTINATION, destination), (ID, id)];
+                match ack {
+                    AckMode::Auto => {}
+                    AckMode::Client => headers.push((ACK, "client".into())),
+                    AckMode::ClientIndividual => headers.push((ACK, "client-individual".into())),
+                }
+                ("SUBSCRIBE", headers, String::new())
+            }
+            StompClientFrame::UNSUBSCRIBE(id) => ("UNSUBSCRIBE", vec![(ID, id)], String::new()),
+            StompClientFrame::ACK(id) => ("ACK", vec![(ID, id)], String::new()),
+            StompClientFrame::NACK(id) => ("NACK", vec![(ID, id)], String::new()),
+            StompClientFrame::BEGIN(transaction) => {
+                ("BEGIN", vec![(TRANSACTION, transaction)], String::new())
+            }
+            StompClientFrame::COMMIT(transaction) => {
+                ("COMMIT", vec![(TRANSACTION, transaction)], String::new())
+            }
+            StompClientFrame::ABORT(transaction) => {
+                ("ABORT", vec![(TRANSACTION, transaction)], String::new())
+            }
+            StompClientFrame::DISCONNECT => ("DISCONNECT", vec![], String::new()),
+        };
+
+        headers.extend(client_frame.transaction.map(|value| (TRANSACTION, value)));
+        headers.extend(client_frame.receipt.map(|value| (RECEIPT, value)));
+
+        StompFrame {
+            command: command.to_owned(),
+            headers: headers
+                .into_iter()
+                .map(|(key, value)| (key.to_owned(), value.into_owned()))
+                .collect(),
+            text,
+        }
+        .with_metadata(&metadata)
+    }
+}
+
+fn connect_headers(frame: ConnectClientFrame<'_>) -> Vec<(&'static str, Cow<'_, str>)> {
+    let mut headers = vec![(ACCEPT_VERSION, frame.accept_version.join(",").into())];
+
+    headers.extend(frame.host.map(|host| (HOST, host)));
+    headers.extend(frame.login.map(|login| (LOGIN, login)));
+    headers.extend(frame.passcode.map(|passcode| (PASSCODE, passcode)));
+    headers.push((HEART_BEAT, frame.heart_beat.to_string().into()));
+
+    headers
+}
+
+impl From<StompFrame> for String {
+    fn from(frame: StompFrame) -> String {
+        frame.to_encoded_string()
+    }
+}
+
+/// Frame serialized once and delivered to many recipients, cloning it only bumps a reference count.
+/// Headers that differ between deliveries (e.g. `subscription`) are added when a single delivery
+/// is serialized, they are put right after the command so they take precedence over shared headers.
+///
+/// # Examples
+/// ```
+/// use commenter_stomp::stomp::{BroadcastFrame, StompFrame};
+///
+/// let frame = BroadcastFrame::new(&StompFrame::broadcast_message("article-42", "message-1", "Hi"));
+///
+/// assert!(frame
+///     .to_string_with(&[("subscription", "sub-1")])
+///     .starts_with("MESSAGE\nsubscription:sub-1\ndestination:article-42\nmessage-id:message-1\n"));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BroadcastFrame {
+    /// Serialized frame, including NUL terminator.
+    encoded: Arc<str>,
+    /// Length of the command line, headers of a delivery are inserted after it.
+    command_length: usize,
+    escaped: bool,
+}
+
+impl BroadcastFrame {
+    pub fn new(frame: &StompFrame) -> BroadcastFrame {
+        BroadcastFrame {
+            encoded: frame.to_encoded_string().into(),
+            command_length: frame.command.len() + 1,
+            escaped: is_escaped(&frame.command),
+        }
+    }
+
+    /// Serializes delivery of the frame with additional `headers` into `dst`.
+    pub fn encode_with<B: BufMut>(&self, headers: &[(&str, &str)], dst: &mut B) {
+        let (command, rest) = self.encoded.split_at(self.command_length);
+
+        dst.put_slice(command.as_bytes());
+        for (key, value) in headers {
+            infallible(put_header(&mut BufSink(dst), key, value, self.escaped));
+        }
+        dst.put_slice(rest.as_bytes());
+    }
+
+    /// Same as [`encode_with`](BroadcastFrame::encode_with), returning the delivery as a string.
+    /// Headers are written straight into the returned string, the rest of the frame is copied
+    /// into it as it is. Text websocket messages own their payload, so this single copy is
+    /// what every recipient costs.
+    pub fn to_string_with(&self, headers: &[(&str, &str)]) -> String {
+        let (command, rest) = self.encoded.split_at(self.command_length);
+        // unless they need escaping, headers take their length plus colon and EOL
+        let headers_length: usize = headers
+            .iter()
+            .map(|(key, value)| key.len() + value.len() + 2)
+            .sum();
+
+        let mut data = String::with_capacity(self.encoded.len() + headers_length);
+        data.push_str(command);
+        for (key, value) in headers {
+            infallible(put_header(&mut data, key, value, self.escaped));
+        }
+        data.push_str(rest);
+        data
+    }
+}
+
+/// CONNECT and CONNECTED frames are exempt from escaping for backward compatibility with STOMP 1.0.
+fn is_escaped(command: &str) -> bool {
+    !matches!(command, "CONNECT" | "STOMP" | "CONNECTED")
+}
+
+/// Destination frames are serialized into, either a buffer, a writer or a string. Frames are
+/// put in pieces of text, escaping only ever splits them at ASCII characters.
+trait Sink {
+    type Error;
+
+    fn put(&mut self, text: &str) -> std::result::Result<(), Self::Error>;
+}
+
+struct BufSink<'a, B>(&'a mut B);
+
+impl<B: BufMut> Sink for BufSink<'_, B> {
+    type Error = Infallible;
+
+    fn put(&mut self, text: &str) -> std::result::Result<(), Infallible> {
+        self.0.put_slice(text.as_bytes());
+        Ok(())
+    }
+}
+
+struct WriteSink<'a, W>(&'a mut W);
+
+impl<W: io::Write> Sink for WriteSink<'_, W> {
+    type Error = io::Error;
+
+    fn put(&mut self, text: &str) -> io::Result<()> {
+        self.0.write_all(text.as_bytes())
+    }
+}
+
+impl Sink for String {
+    type Error = Infallible;
+
+    fn put(&mut self, text: &str) -> std::result::Result<(), Infallible> {
+        self.push_str(text);
+        Ok(())
+    }
+}
+
+/// Unwraps result of serializing into a sink that can not fail.
+fn infallible(result: std::result::Result<(), Infallible>) {
+    match result {
+        Ok(()) => {}
+        Err(never) => match never {},
+    }
+}
+
+fn put_header<S: Sink>(
+    dst: &mut S,
+    key: &str,
+    value: &str,
+    escaped: bool,
+) -> std::result::Result<(), S::Error> {
+    if escaped {
+        put_escaped(dst, key)?;
+        dst.put(":")?;
+        put_escaped(dst, value)?;
+    } else {
+        dst.put(key)?;
+        dst.put(":")?;
+        dst.put(value)?;
+    }
+
+    dst.put("\n")
+}
+
+fn put_escaped<S: Sink>(dst: &mut S, value: &str) -> std::result::Result<(), S::Error> {
+    let mut rest = value;
+
+    // copy runs of characters that do not need escaping in one go
+    while let Some(position) = rest
+        .bytes()
+        .position(|byte| matches!(byte, b'\r' | b'\n' | b':' | b'\\'))
+    {
+        dst.put(&rest[..position])?;
+        dst.put(match rest.as_bytes()[position] {
+            b'\r' => "\\r",
+            b'\n' => "\\n",
+            b':' => "\\c",
+            _ => "\\\\",
+        })?;
+        rest = &rest[position + 1..];
+    }
+
+    dst.put(rest)
+}
+
+/// Encodes header name or value according to STOMP 1.2 escaping rules.
+///
+/// # Examples
+/// ```
+/// use commenter_stomp::stomp::escape_header;
+///
+/// assert_eq!(escape_header("article:42\n"), "article\\c42\\n");
+/// ```
+pub fn escape_header(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    infallible(put_escaped(&mut escaped, value));
+    escaped
+}
+
+/// Decodes header name or value according to STOMP 1.2 escaping rules.
+/// Undefined escape sequences (e.g. `\t`) are treated as fatal protocol error.
+///
+/// # Examples
+/// ```
+/// use commenter_stomp::stomp::unescape_header;
+///
+/// assert_eq!(unescape_header("article\\c42").unwrap(), "article:42");
+/// assert!(unescape_header("article\\t42").is_err());
+/// ```
+pub fn unescape_header(value: &str) -> Result<Cow<'_, str>> {
+    if !value.contains('\\') {
+        return Ok(Cow::Borrowed(value));
+    }
+
+    let mut unescaped = String::with_capacity(value.len());
+    let mut characters = value.chars();
+
+    while let Some(character) = characters.next() {
+        if character != '\\' {
+            unescaped.push(character);
+            continue;
+        }
+
+        match characters.next() {
+            Some('r') => unescaped.push('\r'),
+            Some('n') => unescaped.push('\n'),
+            Some('c') => unescaped.push(':'),
+            Some('\\') => unescaped.push('\\'),
+            Some(_) | None => return Err(StompParseError::InvalidEscapeSequence(value.to_owned())),
+        }
+    }
+
+    Ok(Cow::Owned(unescaped))
+}
+
+fn owned(value: Cow<'_, str>) -> Cow<'static, str> {
+    Cow::Owned(value.into_owned())
+}
+
+/// Headers of a received frame, borrowed from the received data. Names and values are unescaped
+/// once, when the block is validated, which allocates only for those that actually contain escape
+/// sequences. When a header is repeated, only its first occurrence counts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Headers<'a> {
+    headers: Vec<(Cow<'a, str>, Cow<'a, str>)>,
+}
+
+impl<'a> Headers<'a> {
+    /// Validates header lines of the block, `escaped` tells whether STOMP escaping applies to them.
+    ///
+    /// # Examples
+    /// ```
+    /// use commenter_stomp::stomp::Headers;
+    ///
+    /// let headers = Headers::new("destination:article\\c42\r\nid:sub-1\nid:sub-2", true).unwrap();
+    /// assert_eq!(headers.get("destination").unwrap(), "article:42");
+    /// assert_eq!(headers.get("id").unwrap(), "sub-1");
+    /// assert_eq!(headers.iter().count(), 3);
+    /// ```
+    pub fn new(block: &'a str, escaped: bool) -> Result<Headers<'a>> {
+        let mut headers = Vec::new();
+
+        for line in block.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                return Err(StompParseError::MalformedHeader(line.to_owned()));
+            };
+
+            headers.push(if escaped {
+                (unescape_header(key)?, unescape_header(value)?)
+            } else {
+                (Cow::Borrowed(key), Cow::Borrowed(value))
+            });
+        }
+
+        Ok(Headers { headers })
+    }
+
+    /// Value of the first header called `name`.
+    pub fn get(&self, name: &str) -> Option<Cow<'a, str>> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.clone())
+    }
+
+    /// Headers in the order they were received.
+    pub fn iter(&self) -> impl Iterator<Item = (Cow<'a, str>, Cow<'a, str>)> + '_ {
+        self.headers.iter().cloned()
+    }
+
+    fn require(&self, name: &'static str, command: &'static str) -> Result<Cow<'a, str>> {
+        self.get(name).ok_or(StompParseError::MissingHeader {
+            header: name,
+            command,
+        })
+    }
+}
+
+/// Counts EOLs (heart-beats) that can precede a frame.
+pub(crate) fn leading_eols(raw: &[u8]) -> usize {
+    let mut position = 0;
+
+    loop {
+        if raw[position..].starts_with(b"\n") {
+            position += 1;
+        } else if raw[position..].starts_with(b"\r\n") {
+            position += 2;
+        } else {
+            return position;
+        }
+    }
+}
+
+/// Reads single EOL terminated line starting at `position` and moves `position` past the EOL.
+/// Returned line does not contain EOL (`\n` or `\r\n`). `None` means that EOL was not received yet.
+fn read_line<'a>(raw: &'a [u8], position: &mut usize) -> Option<&'a [u8]> {
+    let length = raw[*position..].iter().position(|byte| *byte == b'\n')?;
+
+    let line = &raw[*position..*position + length];
+    *position += length + 1;
+
+    Some(line.strip_suffix(b"\r").unwrap_or(line))
+}
+
+/// Reads header lines starting at `position` up to and including the empty line ending them,
+/// returned block does not contain the empty line. `None` means that the block was not fully received yet.
+fn read_header_block<'a>(
+    raw: &'a [u8],
+    position: &mut usize,
+    limits: &ParserLimits,
+) -> Result<Option<&'a [u8]>> {
+    let block_start = *position;
+    let mut header_count = 0;
+
+    loop {
+        let line_start = *position;
+
+        let Some(header_line) = read_line(raw, position) else {
+            // partial line may still end with \r that is not counted in its length
+            if raw.len() - line_start > limits.max_header_line_length.saturating_add(1) {
+                return Err(StompParseError::HeaderLineTooLong(
+                    limits.max_header_line_length,
+                ));
+            }
+            return Ok(None);
+        };
+
+        if header_line.is_empty() {
+            return Ok(Some(&raw[block_start..line_start]));
+        }
+
+        if header_line.len() > limits.max_header_line_length {
+            return Err(StompParseError::HeaderLineTooLong(
+                limits.max_header_line_length,
+            ));
+        }
+
+        header_count += 1;
+        if header_count > limits.max_headers {
+            return Err(StompParseError::TooManyHeaders(limits.max_headers));
+        }
+    }
+}
+
+/// Locates frame body starting at `position`. When `content-length` is present exactly that many
+/// octets are read (body can contain NUL octets) and NUL has to follow them, otherwise body ends
+/// on first NUL octet. `None` means that body was not fully received yet.
+/// Body longer than `max_length` is rejected as soon as that is known.
+fn read_body<'a>(
+    raw: &'a [u8],
+    position: usize,
+    content_length: Option<&str>,
+    max_length: usize,
+) -> Result<Option<&'a [u8]>> {
+    let end = match content_length {
+        Some(content_length) => {
+            let invalid_content_length = || StompParseError::InvalidHeaderValue {
+                header: CONTENT_LENGTH,
+                value: content_length.to_owned(),
+            };
+
+            let length = content_length
+                .parse::<usize>()
+                .map_err(|_| invalid_content_length())?;
+
+            if length > max_length {
+                return Err(StompParseError::BodyTooLarge(max_length));
+            }
+
+            let end = position
+                .checked_add(length)
+                .ok_or_else(invalid_content_length)?;
+
+            match raw.get(end) {
+                Some(0) => end,
+                Some(_) => return Err(StompParseError::ContentLengthMismatch(length)),
+                None => return Ok(None),
+            }
+        }
+        None => match raw[position..].iter().position(|byte| *byte == 0) {
+            Some(length) if length > max_length => {
+                return Err(StompParseError::BodyTooLarge(max_length))
+            }
+            Some(length) => position + length,
+            None if raw.len() - position > max_length => {
+                return Err(StompParseError::BodyTooLarge(max_length))
+            }
+            None => return Ok(None),
+        },
+    };
+
+    Ok(Some(&raw[position..end]))
+}
+
+impl<'a> StompClientFrame<'a> {
+    /// Parses data that carries exactly one frame, optionally followed by EOLs.
+    /// Returned frame borrows from `raw`.
+    ///
+    /// # Examples
+    /// ```
+    /// use spectral::prelude::*;
+    /// use commenter_stomp::stomp::StompClientFrame;
+    ///
+    /// let result = StompClientFrame::parse(b"DISCONNECT\n\n\0");
+    /// assert_that(&result).is_ok_containing(StompClientFrame::DISCONNECT);
+    /// ```
+    #[inline]
+    pub fn parse(raw: &'a [u8]) -> Result<StompClientFrame<'a>> {
+        StompClientFrame::parse_with_limits(raw, &ParserLimits::default())
+    }
+
+    /// Same as [`parse`](StompClientFrame::parse), rejecting frames that exceed `limits`.
+    pub fn parse_with_limits(raw: &'a [u8], limits: &ParserLimits) -> Result<StompClientFrame<'a>> {
+        match StompClientFrame::decode_with_limits(raw, limits)? {
+            Some((client_frame, length)) if leading_eols(&raw[length..]) == raw.len() - length => {
+                Ok(client_frame.frame)
+            }
+            Some(_) => Err(StompParseError::TrailingData),
+            None => Err(StompParseError::IncompleteFrame),
+        }
+    }
+
+    /// Decodes first frame from the beginning of `raw`, skipping EOLs (heart-beats) preceding it.
+    /// Returns `None` when `raw` does not contain a complete frame yet, otherwise the frame (with its
+    /// `receipt` header) together with number of bytes it occupied (up to and including the NUL terminator).
+    ///
+    /// # Examples
+    /// ```
+    /// use commenter_stomp::stomp::StompClientFrame;
+    ///
+    /// let raw = b"\nDISCONNECT\n\n\0SUBSCRIBE\n";
+    /// let (client_frame, length) = StompClientFrame::decode(raw).unwrap().unwrap();
+    /// assert_eq!(client_frame.frame, StompClientFrame::DISCONNECT);
+    /// assert!(StompClientFrame::decode(&raw[length..]).unwrap().is_none());
+    /// ```
+    pub fn decode(raw: &'a [u8]) -> Result<Option<(ClientFrame<'a>, usize)>> {
+        StompClientFrame::decode_with_limits(raw, &ParserLimits::default())
+    }
+
+    /// Same as [`decode`](StompClientFrame::decode), rejecting frames that exceed `limits`.
+    /// Incomplete frame is rejected as soon as received part of it exceeds them.
+    pub fn decode_with_limits(
+        raw: &'a [u8],
+        limits: &ParserLimits,
+    ) -> Result<Option<(ClientFrame<'a>, usize)>> {
+        StompClientFrame::decode_resumable(raw, limits, &mut Missing::Unknown)
+    }
+
+    /// Same as [`decode_with_limits`](StompClientFrame::decode_with_limits) for data that only
+    /// grows between calls, `missing` is what the frame was waiting for at the previous call.
+    /// Data is not parsed again until that arrives, which keeps a frame received in many small
+    /// chunks from being parsed from the start for each of them.
+    pub(crate) fn decode_resumable(
+        raw: &'a [u8],
+        limits: &ParserLimits,
+        missing: &mut Missing,
+    ) -> Result<Option<(ClientFrame<'a>, usize)>> {
+        // COMMAND[\r]\n
+        // HEADER[\r]\n
+        //   .
+        //   .
+        // HEADER[\r]\n
+        // [\r]\n
+        // BODY..
+        // \0
+
+        if !missing.may_have_arrived(raw, limits) {
+            return Ok(None);
+        }
+        *missing = Missing::Unknown;
+
+        let frame_start = leading_eols(raw);
+        let mut position = frame_start;
+
+        // more data can only make the frame longer
+        let mut incomplete = |waiting_for| {
+            *missing = waiting_for;
+
+            if raw.len() - frame_start > limits.max_frame_length {
+                Err(StompParseError::FrameTooLarge(limits.max_frame_length))
+            } else {
+                Ok(None)
+            }
+        };
+
+        let Some(command) = read_line(raw, &mut position) else {
+            return incomplete(Missing::Eol {
+                start: frame_start,
+                scanned: raw.len(),
+            });
+        };
+
+        let command = str::from_utf8(command)?;
+
+        let Some(header_block) = read_header_block(raw, &mut position, limits)? else {
+            // position is left at the start of the partial header line
+            return incomplete(Missing::Eol {
+                start: position,
+                scanned: raw.len(),
+            });
+        };
+
+        let headers = Headers::new(str::from_utf8(header_block)?, is_escaped(command))?;
+        let content_length = headers.get(CONTENT_LENGTH);
+
+        let Some(body) = read_body(
+            raw,
+            position,
+            content_length.as_deref(),
+            limits.max_body_length,
+        )?
+        else {
+            // content-length that is not a valid length was already rejected by read_body
+            let waiting_for = match content_length.and_then(|length| length.parse().ok()) {
+                Some(length) => Missing::Length(position.saturating_add(length).saturating_add(1)),
+                None => Missing::Nul {
+                    start: position,
+                    scanned: raw.len(),
+                },
+            };
+            return incomplete(waiting_for);
+        };
+
+        let length = position + body.len() + 1;
+
+        if length - frame_start > limits.max_frame_length {
+            return Err(StompParseError::FrameTooLarge(limits.max_frame_length));
+        }
+        let receipt = headers.get(RECEIPT);
+
+        let frame = match command {
+            "CONNECT" => {
+                StompClientFrame::CONNECT(StompClientFrame::create_connect_frame(&headers)?)
+            }
+            "STOMP" => StompClientFrame::STOMP(StompClientFrame::create_connect_frame(&headers)?),
+            "SEND" => StompClientFrame::crate_send_frame(&headers, body)?,
+            "SUBSCRIBE" => StompClientFrame::create_subscribe_frame(&headers)?,
+            "UNSUBSCRIBE" => StompClientFrame::create_unsubscribe_frame(&headers)?,
+            "ACK" => StompClientFrame::ACK(headers.require(ID, "ACK")?),
+            "NACK" => StompClientFrame::NACK(headers.require(ID, "NACK")?),
+            "BEGIN" => StompClientFrame::BEGIN(headers.require(TRANSACTION, "BEGIN")?),
+            "COMMIT" => StompClientFrame::COMMIT(headers.require(TRANSACTION, "COMMIT")?),
+            "ABORT" => StompClientFrame::ABORT(headers.require(TRANSACTION, "ABORT")?),
+            "DISCONNECT" => StompClientFrame::DISCONNECT,
+            "" => return Err(StompParseError::MissingCommand),
+            _ => return Err(StompParseError::UnknownCommand(command.to_owned())),
+        };
+
+        let transaction = match frame {
+            StompClientFrame::SEND(_) | StompClientFrame::ACK(_) | StompClientFrame::NACK(_) => {
+                headers.get(TRANSACTION)
+            }
+            _ => None,
+        };
+
+        Ok(Some((
+            ClientFrame {
+                frame,
+                receipt,
+                transaction,
+            },
+            length,
+        )))
+    }
+
+    /// Copies borrowed data, so the frame can outlive the buffer it was parsed from.
+    pub fn into_owned(self) -> StompClientFrame<'static> {
+        match self {
+            StompClientFrame::CONNECT(frame) => StompClientFrame::CONNECT(frame.into_owned()),
+            StompClientFrame::STOMP(frame) => StompClientFrame::STOMP(frame.into_owned()),
+            StompClientFrame::SEND(frame) => StompClientFrame::SEND(frame.into_owned()),
+            StompClientFrame::SUBSCRIBE {
+                destination,
+                id,
+                ack,
+            } => StompClientFrame::SUBSCRIBE {
+                destination: owned(destination),
+                id: owned(id),
+                ack,
+            },
+            StompClientFrame::UNSUBSCRIBE(id) => StompClientFrame::UNSUBSCRIBE(owned(id)),
+            StompClientFrame::ACK(id) => StompClientFrame::ACK(owned(id)),
+            StompClientFrame::NACK(id) => StompClientFrame::NACK(owned(id)),
+            StompClientFrame::BEGIN(transaction) => StompClientFrame::BEGIN(owned(transaction)),
+            StompClientFrame::COMMIT(transaction) => StompClientFrame::COMMIT(owned(transaction)),
+            StompClientFrame::ABORT(transaction) => StompClientFrame::ABORT(owned(transaction)),
+            StompClientFrame::DISCONNECT => StompClientFrame::DISCONNECT,
+        }
+    }
+
+    fn create_connect_frame(headers: &Headers<'a>) -> Result<ConnectClientFrame<'a>> {
+        let accept_version = match headers.get(ACCEPT_VERSION) {
+            Some(Cow::Borrowed(versions)) => versions
+                .split(',')
+                .map(|v| Cow::Borrowed(v.trim()))
+                .collect(),
+            Some(Cow::Owned(versions)) => versions
+                .split(',')
+                .map(|v| Cow::Owned(v.trim().to_owned()))
+                .collect(),
+            None => vec![Cow::Borrowed("1.0")],
+        };
+
+        let heart_beat = match headers.get(HEART_BEAT) {
+            Some(heart_beat) => StompClientFrame::parse_heart_beat(&heart_beat)?,
+            None => HeartBeat::default(),
+        };
+
+        Ok(ConnectClientFrame {
+            accept_version,
+            host: headers.get(HOST),
+            login: headers.get(LOGIN),
+            passcode: headers.get(PASSCODE),
+            heart_beat,
+        })
+    }
+
+    fn parse_heart_beat(value: &str) -> Result<HeartBeat> {
+        let heart_beat = value.split_once(',').and_then(|(outgoing, incoming)| {
+            Some(HeartBeat {
+                outgoing: outgoing.parse().ok()?,
+                incoming: incoming.parse().ok()?,
+            })
+        });
+
+        heart_beat.ok_or_else(|| StompParseError::InvalidHeaderValue {
+            header: HEART_BEAT,
+            value: value.to_owned(),
+        })
+    }
+
+    fn crate_send_frame(headers: &Headers<'a>, payload: &'a [u8]) -> Result<StompClientFrame<'a>> {
+        let action = headers.require(ACTION, "SEND")?;
+
+        let send_frame = match action.as_ref() {
+            "CREATE" => {
+                StompClientFrame::create_send_create_frame(headers, str::from_utf8(payload)?)
+            }
+            "UPDATE" => {
+                StompClientFrame::create_send_update_frame(headers, str::from_utf8(payload)?)
+            }
+            "DELETE" => StompClientFrame::create_send_delete_frame(headers),
+            _ => return Err(StompParseError::UnknownAction(action.into_owned())),
+        }?;
+
+        Ok(StompClientFrame::SEND(send_frame))
+    }
+
+    fn create_send_create_frame(
+        headers: &Headers<'a>,
+        text: &'a str,
+    ) -> Result<SendClientFrame<'a>> {
+        Ok(SendClientFrame::CREATE {
+            destination: headers.require(DESTINATION, "SEND")?,
+            text: Cow::Borrowed(text),
+            metadata: SendMetadata::from_headers(headers.iter()),
+        })
+    }
+
+    fn create_send_update_frame(
+        headers: &Headers<'a>,
+        text: &'a str,
+    ) -> Result<SendClientFrame<'a>> {
+        Ok(SendClientFrame::UPDATE {
+            id: headers.require(ID, "SEND")?,
+            text: Cow::Borrowed(text),
+            metadata: SendMetadata::from_headers(headers.iter()),
+        })
+    }
+
+    fn create_send_delete_frame(headers: &Headers<'a>) -> Result<SendClientFrame<'a>> {
+        Ok(SendClientFrame::DELETE {
+            id: headers.require(ID, "SEND")?,
+            metadata: SendMetadata::from_headers(headers.iter()),
+        })
+    }
+
+    fn create_subscribe_frame(headers: &Headers<'a>) -> Result<StompClientFrame<'a>> {
+        let ack = match headers.get(ACK).as_deref() {
+            None | Some("auto") => AckMode::Auto,
+            Some("client") => AckMode::Client,
+            Some("client-individual") => AckMode::ClientIndividual,
+            Some(other) => {
+                return Err(StompParseError::InvalidHeaderValue {
+                    header: ACK,
+                    value: other.to_owned(),
+                })
+            }
+        };
+
+        Ok(StompClientFrame::SUBSCRIBE {
+            destination: headers.require(DESTINATION, "SUBSCRIBE")?,
+            id: headers.require(ID, "SUBSCRIBE")?,
+            ack,
+        })
+    }
+
+    fn create_unsubscribe_frame(headers: &Headers<'a>) -> Result<StompClientFrame<'a>> {
+        Ok(StompClientFrame::UNSUBSCRIBE(
+            headers.require(ID, "UNSUBSCRIBE")?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use spectral::prelude::*;
+    use std::collections::HashMap;
+
+    mod disconnect {
+        use super::*;
+
+        #[test]
+        fn stomp_client_frame_should_parse_disconnection_message_with_new_line_as_eol() {
+            test_stomp_client_frame_disconnection_message_parsing(false);
+        }
+
+        #[test]
+        fn stomp_client_frame_should_parse_disconnection_message_with_carriage_return_included_in_eol(
+        ) {
+            test_stomp_client_frame_disconnection_message_parsing(true)
+        }
+
+        fn test_stomp_client_frame_disconnection_message_parsing(optional_carraige_return: bool) {
+            let input = encode_stomp_frame_command_only("DISCONNECT", optional_carraige_return);
+            test_stomp_client_frame_parsing(input, StompClientFrame::DISCONNECT);
+        }
+    }
+
+    mod connect {
+        use super::*;
+
+        #[test]
+        fn stomp_client_frame_should_parse_connect_message_with_new_line_as_eol() {
+            test_stomp_client_frame_connect_message_parsing("CONNECT", false)
+        }
+
+        #[test]
+        fn stomp_client_frame_should_parse_connect_message_with_carriage_return_included_in_eol() {
+            test_stomp_client_frame_connect_message_parsing("CONNECT", true)
+        }
+
+        #[test]
+        fn stomp_client_frame_should_parse_stomp_message_with_new_line_as_eol() {
+            test_stomp_client_frame_connect_message_parsing("STOMP", false)
+        }
+
+        #[test]
+        fn stomp_client_frame_should_parse_stomp_message_with_carriage_return_included_in_eol() {
+            test_stomp_client_frame_connect_message_parsing("STOMP", true)
+        }
+
+        #[test]
+        fn stomp_client_frame_should_assume_stomp_1_0_when_accept_version_is_missing() {
+            let input = encode_stomp_frame_command_only("CONNECT", false);
+
+            test_stomp_client_frame_parsing(
+                input,
+                StompClientFrame::CONNECT(ConnectClientFrame {
+                    accept_version: vec!["1.0".into()],
+                    host: None,
+                    login: None,
+                    passcode: None,
+                    heart_beat: HeartBeat::default(),
+                }),
+            );
+        }
+
+        #[test]
+        fn stomp_client_frame_should_reject_connect_message_with_malformed_heart_beat() {
+            let input = encode_stomp_frame_command_with_headers(
+                "CONNECT",
+                HashMap::from([(ACCEPT_VERSION, "1.2"), (HEART_BEAT, "10")]),
+                false,
+            );
+
+            test_stomp_client_frame_parsing_failure(
+                &input,
+                StompParseError::InvalidHeaderValue {
+                    header: HEART_BEAT,
+                    value: "10".to_owned(),
+                },
+            );
+        }
+
+        #[test]
+        fn connect_client_frame_should_negotiate_highest_common_version() {
+            let frame = connect_client_frame(vec!["1.0", "1.1", "1.2"]);
+            assert_that(&frame.negotiate_version())
+                .is_some()
+                .is_equal_to("1.2");
+        }
+
+        #[test]
+        fn connect_client_frame_should_not_negotiate_version_when_none_is_supported() {
+            let frame = connect_client_frame(vec!["1.0", "1.1"]);
+            assert_that(&frame.negotiate_version()).is_none();
+        }
+
+        fn test_stomp_client_frame_connect_message_parsing(
+            command: &str,
+            optional_carraige_return: bool,
+        ) {
+            let headers = HashMap::from([
+                (ACCEPT_VERSION, "1.1,1.2"),
+                (HOST, "commenter"),
+                (LOGIN, "user"),
+                (PASSCODE, "secret"),
+                (HEART_BEAT, "1000,2000"),
+            ]);
+            let input =
+                encode_stomp_frame_command_with_headers(command, headers, optional_carraige_return);
+
+            let connect_frame = ConnectClientFrame {
+                accept_version: vec!["1.1".into(), "1.2".into()],
+                host: Some("commenter".into()),
+                login: Some("user".into()),
+                passcode: Some("secret".into()),
+                heart_beat: HeartBeat {
+                    outgoing: 1000,
+                    incoming: 2000,
+                },
+            };
+
+            let output = if command == "STOMP" {
+                StompClientFrame::STOMP(connect_frame)
+            } else {
+                StompClientFrame::CONNECT(connect_frame)
+            };
+
+            test_stomp_client_frame_parsing(input, output);
+        }
+
+        fn connect_client_frame(accept_version: Vec<&str>) -> ConnectClientFrame<'_> {
+            ConnectClientFrame {
+                accept_version: accept_version.into_iter().map(Cow::from).collect(),
+                host: None,
+                login: None,
+                passcode: None,
+                heart_beat: HeartBeat::default(),
+            }
+        }
+    }
+
+    mod heart_beat {
+        use super::*;
+
+        #[test]
+        fn heart_beat_should_negotiate_greater_of_both_intervals() {
+            let server = HeartBeat {
+                outgoing: 10000,
+                incoming: 5000,
+            };
+            let client = HeartBeat {
+                outgoing: 2000,
+                incoming: 20000,
+            };
+
+            assert_that(&server.negotiate(&client)).is_equal_to(NegotiatedHeartBeat {
+                outgoing: Some(Duration::from_millis(20000)),
+                incoming: Some(Duration::from_millis(5000)),
+            });
+        }
+
+        #[test]
+        fn heart_beat_should_be_disabled_when_either_side_announces_zero() {
+            let server = HeartBeat {
+                outgoing: 0,
+                incoming: 5000,
+            };
+            let client = HeartBeat {
+                outgoing: 0,
+                incoming: 20000,
+            };
+
+            assert_that(&server.negotiate(&client)).is_equal_to(NegotiatedHeartBeat::default());
+        }
+    }
+
+    mod receipt {
+        use super::*;
+
+        #[test]
+        fn stomp_client_frame_should_decode_receipt_header() {
+            let raw = b"SUBSCRIBE\ndestination:topic-1\nid:sub-1\nreceipt:receipt\\c1\n\n\0";
+
+            let result = StompClientFrame::decode(raw);
+
+            assert_that(&result).is_ok().is_some().is_equal_to((
+                ClientFrame {
+                    frame: StompClientFrame::SUBSCRIBE {
+                        destination: "topic-1".into(),
+                        id: "sub-1".into(),
+                        ack: AckMode::Auto,
+                    },
+                    receipt: Some("receipt:1".into()),
+                    transaction: None,
+                },
+                raw.len(),
+            ));
+        }
+
+        #[test]
+        fn stomp_client_frame_should_decode_frame_without_receipt_header() {
+            let raw = b"DISCONNECT\n\n\0";
+
+            let result = StompClientFrame::decode(raw);
+
+            assert_that(&result)
+                .is_ok()
+                .is_some()
+                .is_equal_to((ClientFrame::from(StompClientFrame::DISCONNECT), raw.len()));
+        }
+
+        #[test]
+        fn stomp_frame_should_serialize_receipt_frame() {
+            let frame: String = StompFrame::receipt("receipt-1").into();
+
+            assert_that(&frame).is_equal_to("RECEIPT\nreceipt-id:receipt-1\n\n\0".to_owned());
+        }
+
+        #[test]
+        fn stomp_frame_should_serialize_error_frame_with_excerpt() {
+            let frame: String =
+                StompFrame::error_with_excerpt("Unable to parse header", &[b'x'; 300])
+                    .with_receipt_id("receipt-1")
+                    .into();
+            let excerpt = "x".repeat(256);
+
+            assert_that(&frame).contains("\nmessage:Unable to parse header\n");
+            assert_that(&frame).contains("\nreceipt-id:receipt-1\n");
+            assert_that(&frame).contains("\ncontent-length:282\n");
+            assert_that(&frame)
+                .ends_with(format!("\n\nThe message:\n-----\n{excerpt}\n-----\n\0").as_str());
+        }
+
+        #[test]
+        fn stomp_frame_should_write_nul_terminated_frame() {
+            let mut output = Vec::new();
+
+            let result = StompFrame::receipt("receipt-1").write_to(&mut output);
+
+            assert_that(&result).is_ok();
+            assert_that(&output).is_equal_to(b"RECEIPT\nreceipt-id:receipt-1\n\n\0".to_vec());
+        }
+
+        #[test]
+        fn stomp_frame_should_write_same_bytes_as_encode() {
+            let frame = StompFrame::message("/comments", "sub:1", "message-1", "text");
+            let mut encoded = Vec::new();
+            frame.encode(&mut encoded);
+            let mut output = Vec::new();
+
+            let result = frame.write_to(&mut output);
+
+            assert_that(&result).is_ok();
+            assert_that(&output).is_equal_to(encoded);
+        }
+
+        #[test]
+        fn stomp_frame_should_report_failed_write() {
+            let mut output = [0u8; 8];
+
+            let result = StompFrame::receipt("receipt-1").write_to(&mut &mut output[..]);
+
+            assert_that(&result).is_err();
+        }
+
+        #[test]
+        fn stomp_frame_should_serialize_receipt_error_frame() {
+            let frame: String = StompFrame::receipt_error("failure", "receipt-1").into();
+
+            assert_that(&frame).starts_with("ERROR\n");
+            assert_that(&frame).contains("\nreceipt-id:receipt-1\n");
+            assert_that(&frame).contains("\nmessage:failure\n");
+        }
+    }
+
+    mod connected {
+        use super::*;
+
+        #[test]
+        fn stomp_frame_should_serialize_connected_frame() {
+            let frame: String = StompFrame::connected(
+                "1.2",
+                "commenter-edge",
+                "session-1",
+                &HeartBeat {
+                    outgoing: 5000,
+                    incoming: 10000,
+                },
+            )
+            .into();
+
+            assert_that(&frame).starts_with("CONNECTED\n");
+            assert_that(&frame).contains("\nversion:1.2\n");
+            assert_that(&frame).contains("\nserver:commenter-edge\n");
+            assert_that(&frame).contains("\nsession:session-1\n");
+            assert_that(&frame).contains("\nheart-beat:5000,10000\n");
+        }
+
+        #[test]
+        fn stomp_frame_should_serialize_unsupported_version_error_frame() {
+            let frame: String = StompFrame::unsupported_version().into();
+
+            assert_that(&frame).starts_with("ERROR\n");
+            assert_that(&frame).contains("\nversion:1.2\n");
+        }
+    }
+
+    mod escaping {
+        use super::*;
+
+        #[test]
+        fn stomp_client_frame_should_split_header_on_first_colon() {
+            let input = encode_stomp_frame_command_with_headers(
+                "SUBSCRIBE",
+                HashMap::from([(DESTINATION, "article:42"), (ID, "sub-1")]),
+                false,
+            );
+
+            test_stomp_client_frame_parsing(
+                input,
+                StompClientFrame::SUBSCRIBE {
+                    destination: "article:42".into(),
+                    id: "sub-1".into(),
+                    ack: AckMode::Auto,
+                },
+            );
+        }
+
+        #[test]
+        fn stomp_client_frame_should_unescape_header_values() {
+            let input = encode_stomp_frame_command_with_headers(
+                "SUBSCRIBE",
+                HashMap::from([(DESTINATION, "article\\c42\\n\\r\\\\"), (ID, "sub\\c1")]),
+                true,
+            );
+
+            test_stomp_client_frame_parsing(
+                input,
+                StompClientFrame::SUBSCRIBE {
+                    destination: "article:42\n\r\\".into(),
+                    id: "sub:1".into(),
+                    ack: AckMode::Auto,
+                },
+            );
+        }
+
+        #[test]
+        fn stomp_client_frame_should_reject_undefined_escape_sequence() {
+            let input = encode_stomp_frame_command_with_headers(
+                "SUBSCRIBE",
+                HashMap::from([(DESTINATION, "article\\t42"), (ID, "sub-1")]),
+                false,
+            );
+
+            test_stomp_client_frame_parsing_failure(
+                &input,
+                StompParseError::InvalidEscapeSequence("article\\t42".to_owned()),
+            );
+        }
+
+        #[test]
+        fn stomp_client_frame_should_reject_unterminated_escape_sequence() {
+            let input = encode_stomp_frame_command_with_headers(
+                "UNSUBSCRIBE",
+                HashMap::from([(ID, "sub-1\\")]),
+                false,
+            );
+
+            test_stomp_client_frame_parsing_failure(
+                &input,
+                StompParseError::InvalidEscapeSequence("sub-1\\".to_owned()),
+            );
+        }
+
+        #[test]
+        fn stomp_client_frame_should_not_unescape_connect_frame_headers() {
+            let input = encode_stomp_frame_command_with_headers(
+                "CONNECT",
+                HashMap::from([(ACCEPT_VERSION, "1.2"), (LOGIN, "us\\er")]),
+                false,
+            );
+
+            let result = StompClientFrame::parse(input.as_bytes());
+
+            assert_that(&result).is_ok_containing(StompClientFrame::CONNECT(ConnectClientFrame {
+                accept_version: vec!["1.2".into()],
+                host: None,
+                login: Some("us\\er".into()),
+                passcode: None,
+                heart_beat: HeartBeat::default(),
+            }));
+        }
+
+        #[test]
+        fn stomp_frame_should_escape_header_values() {
+            let frame: String = StompFrame::error("article:42\n\r\\").into();
+
+            assert_that(&frame)
+                .is_equal_to("ERROR\nmessage:article\\c42\\n\\r\\\\\n\n\0".to_owned());
+        }
+
+        #[test]
+        fn stomp_frame_should_not_escape_connected_frame_headers() {
+            let frame: String =
+                StompFrame::connected("1.2", "edge:1", "session-1", &HeartBeat::default()).into();
+
+            assert_that(&frame).contains("\nserver:edge:1\n");
+        }
+
+        #[test]
+        fn escaped_header_values_should_round_trip() {
+            let destination = "group:1\\2\r\n:";
+            let frame: String = StompFrame {
+                command: "SUBSCRIBE".to_owned(),
+                headers: vec![
+                    (DESTINATION.to_owned(), destination.to_owned()),
+                    (ID.to_owned(), "sub:1".to_owned()),
+                ],
+                text: String::new(),
+            }
+            .into();
+
+            test_stomp_client_frame_parsing(
+                frame,
+                StompClientFrame::SUBSCRIBE {
+                    destination: destination.into(),
+                    id: "sub:1".into(),
+                    ack: AckMode::Auto,
+                },
+            );
+        }
+    }
+
+    mod body {
+        use super::*;
+
+        #[test]
+        fn stomp_client_frame_should_read_body_containing_new_lines() {
+            test_stomp_client_frame_parsing(
+                "SEND\naction:CREATE\ndestination:topic-1\n\nfirst line\nsecond line\n\0",
+                StompClientFrame::SEND(SendClientFrame::CREATE {
+                    destination: "topic-1".into(),
+                    text: "first line\nsecond line\n".into(),
+                    metadata: SendMetadata::default(),
+                }),
+            );
+        }
+
+        #[test]
+        fn stomp_client_frame_should_read_body_containing_nul_when_content_length_is_specified() {
+            test_stomp_client_frame_parsing(
+                "SEND\naction:CREATE\ndestination:topic-1\ncontent-length:5\n\nab\0cd\0",
+                StompClientFrame::SEND(SendClientFrame::CREATE {
+                    destination: "topic-1".into(),
+                    text: "ab\0cd".into(),
+                    metadata: SendMetadata::default(),
+                }),
+            );
+        }
+
+        #[test]
+        fn stomp_client_frame_should_accept_eols_after_nul_terminator() {
+            test_stomp_client_frame_parsing("DISCONNECT\n\n\0\r\n\n", StompClientFrame::DISCONNECT);
+        }
+
+        #[test]
+        fn stomp_client_frame_should_parse_bytes() {
+            let raw = Bytes::from_static(b"SEND\naction:DELETE\nid:101\n\n\0");
+
+            assert_that(&StompClientFrame::parse(&raw)).is_ok_containing(StompClientFrame::SEND(
+                SendClientFrame::DELETE {
+                    id: "101".into(),
+                    metadata: SendMetadata::default(),
+                },
+            ));
+        }
+
+        #[test]
+        fn stomp_client_frame_should_reject_body_not_matching_content_length() {
+            test_stomp_client_frame_parsing_failure(
+                "SEND\naction:CREATE\ndestination:topic-1\ncontent-length:3\n\nabcd\0",
+                StompParseError::ContentLengthMismatch(3),
+            );
+        }
+
+        #[test]
+        fn stomp_client_frame_should_reject_content_length_exceeding_frame() {
+            test_stomp_client_frame_parsing_failure(
+                "SEND\naction:CREATE\ndestination:topic-1\ncontent-length:100\n\nabcd\0",
+                StompParseError::IncompleteFrame,
+            );
+        }
+
+        #[test]
+        fn stomp_client_frame_should_reject_invalid_content_length() {
+            test_stomp_client_frame_parsing_failure(
+                "SEND\naction:CREATE\ndestination:topic-1\ncontent-length:-1\n\nabcd\0",
+                StompParseError::InvalidHeaderValue {
+                    header: CONTENT_LENGTH,
+                    value: "-1".to_owned(),
+                },
+            );
+        }
+
+        #[test]
+        fn stomp_client_frame_should_reject_frame_without_nul_terminator() {
+            test_stomp_client_frame_parsing_failure(
+                "SEND\naction:CREATE\ndestination:topic-1\n\nabcd",
+                StompParseError::IncompleteFrame,
+            );
+        }
+
+        #[test]
+        fn stomp_client_frame_should_reject_frame_without_headers_terminator() {
+            test_stomp_client_frame_parsing_failure(
+                "SUBSCRIBE\ndestination:topic-1\nid:sub-1",
+                StompParseError::IncompleteFrame,
+            );
+        }
+
+        #[test]
+        fn stomp_client_frame_should_reject_data_after_nul_terminator() {
+            test_stomp_client_frame_parsing_failure(
+                "DISCONNECT\n\n\0DISCONNECT\n\n\0",
+                StompParseError::TrailingData,
+            );
+        }
+
+        #[test]
+        fn stomp_client_frame_should_reject_send_frame_with_non_utf8_body() {
+            let raw = b"SEND\naction:CREATE\ndestination:topic-1\n\n\xff\xfe\0";
+
+            assert!(matches!(
+                StompClientFrame::parse(raw),
+                Err(StompParseError::InvalidUtf8(_))
+            ));
+        }
+
+        #[test]
+        fn stomp_frame_should_add_content_headers_to_message_frame() {
+            let frame: String = StompFrame {
+                command: "MESSAGE".to_owned(),
+                headers: Vec::new(),
+                text: "zażółć".to_owned(),
+            }
+            .into();
+
+            assert_that(&frame).contains("\ncontent-length:10\n");
+            assert_that(&frame).contains("\ncontent-type:text/plain;charset=utf-8\n");
+        }
+
+        #[test]
+        fn stomp_frame_should_keep_content_type_of_message_frame() {
+            let frame: String = StompFrame {
+                command: "MESSAGE".to_owned(),
+                headers: vec![(CONTENT_TYPE.to_owned(), "application/json".to_owned())],
+                text: "{}".to_owned(),
+            }
+            .into();
+
+            assert_that(&frame).contains("\ncontent-length:2\n");
+            assert_that(&frame).contains("\ncontent-type:application/json\n");
+        }
+    }
+
+    mod serialization {
+        use super::*;
+        use bytes::BytesMut;
+
+        #[test]
+        fn stomp_frame_should_serialize_headers_in_insertion_order() {
+            let frame: String = StompFrame {
+                command: "MESSAGE".to_owned(),
+                headers: vec![
+                    (DESTINATION.to_owned(), "topic-1".to_owned()),
+                    (ID.to_owned(), "comment-1".to_owned()),
+                    (ACTION.to_owned(), "CREATE".to_owned()),
+                ],
+                text: "text".to_owned(),
+            }
+            .into();
+
+            assert_that(&frame).is_equal_to(
+                "MESSAGE\ndestination:topic-1\nid:comment-1\naction:CREATE\ncontent-length:4\ncontent-type:text/plain;charset=utf-8\n\ntext\0"
+                    .to_owned(),
+            );
+        }
+
+        #[test]
+        fn stomp_frame_should_serialize_message_frame() {
+            let frame: String = StompFrame::message("topic-1", "sub-1", "message-1", "zażółć")
+                .with_header(ACTION, "CREATED")
+                .into();
+
+            assert_that(&frame).is_equal_to(
+                "MESSAGE\ndestination:topic-1\nsubscription:sub-1\nmessage-id:message-1\ncontent-type:text/plain;charset=utf-8\naction:CREATED\ncontent-length:10\n\nzażółć\0"
+                    .to_owned(),
+            );
+        }
+
+        #[test]
+        fn stomp_frame_should_escape_message_frame_headers() {
+            let frame: String = StompFrame::message("article:42", "sub\\1", "message-1", "").into();
+
+            assert_that(&frame).contains("\ndestination:article\\c42\nsubscription:sub\\\\1\n");
+            assert_that(&frame).contains("\ncontent-length:0\n");
+        }
+
+        #[test]
+        fn stomp_frame_should_keep_repeated_headers() {
+            let frame = StompFrame::error("first").with_header(MESSAGE, "second");
+            let serialized: String = frame.clone().into();
+
+            assert_that(&frame.header(MESSAGE))
+                .is_some()
+                .is_equal_to("first");
+            assert_that(&serialized)
+                .is_equal_to("ERROR\nmessage:first\nmessage:second\n\n\0".to_owned());
+        }
+
+        #[test]
+        fn stomp_frame_should_replace_content_length_set_by_hand() {
+            let frame: String = StompFrame {
+                command: "MESSAGE".to_owned(),
+                headers: vec![(CONTENT_LENGTH.to_owned(), "100".to_owned())],
+                text: "text".to_owned(),
+            }
+            .into();
+
+            assert_that(&frame).contains("\ncontent-length:4\n");
+            assert!(!frame.contains("content-length:100"));
+        }
+
+        #[test]
+        fn stomp_frame_should_append_to_reused_buffer() {
+            let mut buffer = BytesMut::new();
+
+            StompFrame::receipt("receipt-1").encode(&mut buffer);
+            StompFrame::receipt("receipt-2").encode(&mut buffer);
+
+            assert_that(&&buffer[..]).is_equal_to(
+                &b"RECEIPT\nreceipt-id:receipt-1\n\n\0RECEIPT\nreceipt-id:receipt-2\n\n\0"[..],
+            );
+
+            buffer.clear();
+            StompFrame::receipt("receipt-3").encode(&mut buffer);
+
+            assert_that(&&buffer[..]).is_equal_to(&b"RECEIPT\nreceipt-id:receipt-3\n\n\0"[..]);
+        }
+
+        #[test]
+        fn broadcast_frame_should_add_delivery_headers_after_command() {
+            let frame = BroadcastFrame::new(
+                &StompFrame::broadcast_message("topic-1", "message-1", "zażółć")
+                    .with_header(ACTION, "CREATED"),
+            );
+
+            assert_that(&frame.to_string_with(&[(SUBSCRIPTION, "sub-1"), (ACK, "ack-1")])).is_equal_to(
+                "MESSAGE\nsubscription:sub-1\nack:ack-1\ndestination:topic-1\nmessage-id:message-1\ncontent-type:text/plain;charset=utf-8\naction:CREATED\ncontent-length:10\n\nzażółć\0"
+                    .to_owned(),
+            );
+        }
+
+        #[test]
+        fn broadcast_frame_should_deliver_the_same_frame_as_message() {
+            let frame = BroadcastFrame::new(&StompFrame::broadcast_message(
+                "article:42",
+                "message-1",
+                "text",
+            ));
+            let mut buffer = BytesMut::new();
+
+            frame.encode_with(&[(SUBSCRIPTION, "sub\\1")], &mut buffer);
+            let (delivered, _) = StompFrame::decode(&buffer).unwrap().unwrap();
+
+            assert_that(&delivered).is_equal_to(StompFrame {
+                command: "MESSAGE".to_owned(),
+                headers: vec![
+                    (SUBSCRIPTION.to_owned(), "sub\\1".to_owned()),
+                    (DESTINATION.to_owned(), "article:42".to_owned()),
+                    (MESSAGE_ID.to_owned(), "message-1".to_owned()),
+                    (CONTENT_TYPE.to_owned(), DEFAULT_CONTENT_TYPE.to_owned()),
+                    (CONTENT_LENGTH.to_owned(), "4".to_owned()),
+                ],
+                text: "text".to_owned(),
+            });
+        }
+
+        #[test]
+        fn broadcast_frame_should_escape_delivery_headers() {
+            let frame =
+                BroadcastFrame::new(&StompFrame::broadcast_message("topic-1", "message-1", ""));
+
+            assert_that(&frame.to_string_with(&[(SUBSCRIPTION, "sub:1\n")]))
+                .starts_with("MESSAGE\nsubscription:sub\\c1\\n\n");
+        }
+    }
+
+    mod errors {
+        use super::*;
+
+        #[test]
+        fn stomp_client_frame_should_reject_unknown_command() {
+            test_stomp_client_frame_parsing_failure(
+                "PUBLISH\n\n\0",
+                StompParseError::UnknownCommand("PUBLISH".to_owned()),
+            );
+        }
+
+        #[test]
+        fn stomp_client_frame_should_reject_malformed_header_line() {
+            test_stomp_client_frame_parsing_failure(
+                "SUBSCRIBE\ndestination\n\n\0",
+                StompParseError::MalformedHeader("destination".to_owned()),
+            );
+        }
+
+        #[test]
+        fn stomp_client_frame_should_reject_unknown_action() {
+            test_stomp_client_frame_parsing_failure(
+                "SEND\naction:PUBLISH\ndestination:topic-1\n\n\0",
+                StompParseError::UnknownAction("PUBLISH".to_owned()),
+            );
+        }
+
+        #[test]
+        fn stomp_client_frame_should_report_missing_header_of_send_frame() {
+            test_stomp_client_frame_parsing_failure(
+                "SEND\ndestination:topic-1\n\ntext\0",
+                StompParseError::MissingHeader {
+                    header: ACTION,
+                    command: "SEND",
+                },
+            );
+            test_stomp_client_frame_parsing_failure(
+                "SEND\naction:CREATE\n\ntext\0",
+                StompParseError::MissingHeader {
+                    header: DESTINATION,
+                    command: "SEND",
+                },
+            );
+            test_stomp_client_frame_parsing_failure(
+                "SEND\naction:DELETE\n\n\0",
+                StompParseError::MissingHeader {
+                    header: ID,
+                    command: "SEND",
+                },
+            );
+        }
+
+        #[test]
+        fn stomp_client_frame_should_report_missing_header_of_subscription_frames() {
+            test_stomp_client_frame_parsing_failure(
+                "SUBSCRIBE\nid:sub-1\n\n\0",
+                StompParseError::MissingHeader {
+                    header: DESTINATION,
+                    command: "SUBSCRIBE",
+                },
+            );
+            test_stomp_client_frame_parsing_failure(
+                "UNSUBSCRIBE\n\n\0",
+                StompParseError::MissingHeader {
+                    header: ID,
+                    command: "UNSUBSCRIBE",
+                },
+            );
+        }
+
+        #[test]
+        fn stomp_parse_error_should_name_header_and_command() {
+            let error = StompParseError::MissingHeader {
+                header: ID,
+                command: "UNSUBSCRIBE",
+            };
+
+            assert_that(&error.to_string())
+                .is_equal_to("UNSUBSCRIBE frame requires id header".to_owned());
+        }
+    }
+
+    mod subscribe {
+        use super::*;
+
+        #[test]
+        fn stomp_client_frame_should_parse_subscribe_message_with_new_line_as_eol() {
+            test_stomp_client_frame_subscribe_message_parsing("topic1", "sub-1", false)
+        }
+
+        #[test]
+        fn stomp_client_frame_should_parse_subscribe_message_with_carriage_return_included_in_eol()
+        {
+            test_stomp_client_frame_subscribe_message_parsing("topic1", "sub-1", true)
+        }
+
+        #[test]
+        fn stomp_client_frame_should_borrow_header_values_from_input() {
+            let input = b"SUBSCRIBE\ndestination:topic-1\nid:sub\\c1\n\n\0";
+
+            let Ok(StompClientFrame::SUBSCRIBE {
+                destination, id, ..
+            }) = StompClientFrame::parse(input)
+            else {
+                panic!("SUBSCRIBE frame expected");
+            };
+
+            assert!(matches!(destination, Cow::Borrowed("topic-1")));
+            // escaped values are the only ones that have to be copied
+            assert!(matches!(id, Cow::Owned(id) if id == "sub:1"));
+        }
+
+        #[test]
+        fn stomp_client_frame_should_use_first_occurrence_of_repeated_header() {
+            test_stomp_client_frame_parsing(
+                "SUBSCRIBE\ndestination:topic-1\nid:sub-1\ndestination:topic-2\n\n\0",
+                StompClientFrame::SUBSCRIBE {
+                    destination: "topic-1".into(),
+                    id: "sub-1".into(),
+                    ack: AckMode::Auto,
+                },
+            );
+        }
+
+        #[test]
+        fn stomp_client_frame_should_outlive_input_once_owned() {
+            let input = String::from("SUBSCRIBE\ndestination:topic-1\nid:sub-1\n\n\0");
+            let frame = StompClientFrame::parse(input.as_bytes())
+                .unwrap()
+                .into_owned();
+            drop(input);
+
+            assert_that(&frame).is_equal_to(StompClientFrame::SUBSCRIBE {
+                destination: "topic-1".into(),
+                id: "sub-1".into(),
+                ack: AckMode::Auto,
+            });
+        }
+
+        fn test_stomp_client_frame_subscribe_message_parsing(
+            destination: &str,
+            id: &str,
+            optional_carraige_return: bool,
+        ) {
+            let headers = HashMap::from([(DESTINATION, destination), (ID, id)]);
+            let input = encode_stomp_frame_command_with_headers(
+                "SUBSCRIBE",
+                headers,
+                optional_carraige_return,
+            );
+
+            test_stomp_client_frame_parsing(
+                input,
+                StompClientFrame::SUBSCRIBE {
+                    destination: destination.into(),
+                    id: id.into(),
+                    ack: AckMode::Auto,
+                },
+            );
+        }
+    }
+
+    mod unsubscribe {
+        use super::*;
+
+        #[test]
+        fn stomp_client_frame_should_parse_unsubscribe_message_with_new_line_as_eol() {
+            test_stomp_client_frame_unsubscribe_message_parsing("sub-1", false)
+        }
+
+        #[test]
+        fn stomp_client_frame_should_parse_unsubscribe_message_with_carriage_return_included_in_eol(
+        ) {
+            test_stomp_client_frame_unsubscribe_message_parsing("sub-1", true)
+        }
+
+        fn test_stomp_client_frame_unsubscribe_message_parsing(
+            id: &str,
+            optional_carraige_return: bool,
+        ) {
+            let input = encode_stomp_frame_command_with_headers(
+                "UNSUBSCRIBE",
+                HashMap::from([(ID, id)]),
+                optional_carraige_return,
+            );
+
+            test_stomp_client_frame_parsing(input, StompClientFrame::UNSUBSCRIBE(id.into()))
+        }
+    }
+
+    mod ack {
+        use super::*;
+
+        #[test]
+        fn stomp_client_frame_should_parse_subscribe_ack_modes() {
+            for (header, ack) in [
+                ("auto", AckMode::Auto),
+                ("client", AckMode::Client),
+                ("client-individual", AckMode::ClientIndividual),
+            ] {
+                test_stomp_client_frame_parsing(
+                    format!("SUBSCRIBE\ndestination:topic-1\nid:sub-1\nack:{header}\n\n\0"),
+                    StompClientFrame::SUBSCRIBE {
+                        destination: "topic-1".into(),
+                        id: "sub-1".into(),
+                        ack,
+                    },
+                );
+            }
+        }
+
+        #[test]
+        fn stomp_client_frame_should_reject_unknown_ack_mode() {
+            test_stomp_client_frame_parsing_failure(
+                "SUBSCRIBE\ndestination:topic-1\nid:sub-1\nack:never\n\n\0",
+                StompParseError::InvalidHeaderValue {
+                    header: ACK,
+                    value: "never".to_owned(),
+                },
+            );
+        }
+
+        #[test]
+        fn stomp_client_frame_should_parse_ack_message() {
+            test_stomp_client_frame_parsing(
+                "ACK\r\nid:message-1\r\n\r\n\0",
+                StompClientFrame::ACK("message-1".into()),
+            );
+        }
 
-#[derive(Clone)]
-pub struct StompFrame {
-    pub command: String,
-    pub headers: HashMap<String, String>,
-    pub text: String,
-}
+        #[test]
+        fn stomp_client_frame_should_parse_nack_message() {
+            test_stomp_client_frame_parsing(
+                "NACK\nid:message-1\n\n\0",
+                StompClientFrame::NACK("message-1".into()),
+            );
+        }
 
-#[derive(PartialEq, Debug)]
-pub enum StompClientFrame {
-    SEND(SendClientFrame),
-    SUBSCRIBE { destination: String, id: String },
-    UNSUBSCRIBE(String),
-    DISCONNECT,
-}
+        #[test]
+        fn stomp_client_frame_should_reject_ack_message_without_id() {
+            test_stomp_client_frame_parsing_failure(
+                "ACK\n\n\0",
+                StompParseError::MissingHeader {
+                    header: ID,
+                    command: "ACK",
+                },
+            );
+        }
 
-#[derive(PartialEq, Debug)]
-pub enum SendClientFrame {
-    CREATE { destination: String, text: String },
-    UPDATE { id: String, text: String },
-    DELETE { id: String },
-}
+        #[test]
+        fn stomp_frame_should_serialize_ack_header_of_message_frame() {
+            let frame: String = StompFrame::message("topic-1", "sub-1", "message-1", "")
+                .with_ack("ack-1")
+                .into();
 
-// impl StompFrame {
-//     pub fn new(comment: &Comment) -> StompFrame {
-//         let state: CommentState = num::FromPrimitive::from_i32(comment.state).unwrap();
+            assert_that(&frame).contains("\nack:ack-1\n");
+        }
+    }
+
+    mod round_trip {
+        use super::*;
+
+        #[test]
+        fn stomp_frame_should_encode_client_frames_parsed_back_unchanged() {
+            let frames = vec![
+                StompClientFrame::CONNECT(ConnectClientFrame {
+                    accept_version: vec!["1.1".into(), "1.2".into()],
+                    host: Some("commenter".into()),
+                    login: Some("user:1".into()),
+                    passcode: None,
+                    heart_beat: HeartBeat {
+                        outgoing: 1000,
+                        incoming: 0,
+                    },
+                }),
+                StompClientFrame::SEND(SendClientFrame::CREATE {
+                    destination: "article:42".into(),
+                    text: "first line\nsecond \0 line".into(),
+                    // body is serialized with the default content-type
+                    metadata: SendMetadata {
+                        content_type: Some(DEFAULT_CONTENT_TYPE.into()),
+                        client_message_id: Some("local:7".into()),
+                        reply_to: None,
+                        custom: vec![("x-locale".into(), "en".into())],
+                    },
+                }),
+                StompClientFrame::SEND(SendClientFrame::UPDATE {
+                    id: "comment-1".into(),
+                    text: "".into(),
+                    metadata: SendMetadata::default(),
+                }),
+                StompClientFrame::SEND(SendClientFrame::DELETE {
+                    id: "comment-1".into(),
+                    metadata: SendMetadata::default(),
+                }),
+                StompClientFrame::SUBSCRIBE {
+                    destination: "article\\42".into(),
+                    id: "sub-1".into(),
+                    ack: AckMode::ClientIndividual,
+                },
+                StompClientFrame::UNSUBSCRIBE("sub-1".into()),
+                StompClientFrame::NACK("ack-1".into()),
+                StompClientFrame::COMMIT("tx-1".into()),
+                StompClientFrame::DISCONNECT,
+            ];
 
-//         StompFrame {
-//             command: "MESSAGE".to_owned(),
-//             headers: HashMap::from([
-//                 (DESTINATION.to_owned(), comment.group_id.clone()),
-//                 (ID.to_owned(), comment.id.clone()),
-//                 (ACTION.to_owned(), state.as_str_name().to_owned()),
-//             ]),
-//             text: comment.text.to_owned(),
-//         }
-//     }
-// }
+            for frame in frames {
+                let mut raw = Vec::new();
+                StompFrame::from(ClientFrame::from(frame.clone())).encode(&mut raw);
+
+                assert_that(&StompClientFrame::parse(&raw)).is_ok_containing(frame);
+            }
+        }
 
-impl Into<String> for StompFrame {
-    fn into(self) -> String {
-        let mut data = Vec::<String>::new();
-        data.push(self.command.clone());
-        data.push(String::from("\n"));
+        #[test]
+        fn stomp_frame_should_encode_receipt_and_transaction_of_client_frame() {
+            let client_frame = ClientFrame {
+                frame: StompClientFrame::ACK("ack-1".into()),
+                receipt: Some("receipt-1".into()),
+                transaction: Some("tx-1".into()),
+            };
+            let mut raw = Vec::new();
+
+            StompFrame::from(client_frame).encode(&mut raw);
+
+            assert_that(&StompClientFrame::decode(&raw))
+                .is_ok()
+                .is_some()
+                .map(|(client_frame, _)| client_frame)
+                .is_equal_to(ClientFrame {
+                    frame: StompClientFrame::ACK("ack-1".into()),
+                    receipt: Some("receipt-1".into()),
+                    transaction: Some("tx-1".into()),
+                });
+        }
 
-        for header in self.headers.iter() {
-            data.push(header.0.clone());
-            data.push(String::from(":"));
-            data.push(header.1.clone());
-            data.push(String::from("\n"));
+        #[test]
+        fn stomp_frame_should_decode_encoded_server_frames() {
+            let frames = vec![
+                StompFrame::connected("1.2", "edge:1", "session-1", &HeartBeat::default()),
+                StompFrame::message("article:42", "sub-1", "message-1", "a\0b").with_ack("ack-1"),
+                StompFrame::receipt("receipt-1"),
+                StompFrame::receipt_error("failure\nline", "receipt-1"),
+            ];
+
+            for frame in frames {
+                let mut raw = Vec::new();
+                frame.encode(&mut raw);
+
+                let (decoded, length) = StompFrame::decode(&raw).unwrap().unwrap();
+                let mut reencoded = Vec::new();
+                decoded.encode(&mut reencoded);
+
+                assert_that(&length).is_equal_to(raw.len());
+                assert_that(&reencoded).is_equal_to(&raw);
+                assert_that(&decoded.header("subscription"))
+                    .is_equal_to(frame.header("subscription"));
+            }
         }
 
-        data.push(String::from("\n"));
-        data.push(self.text.clone());
+        #[test]
+        fn stomp_frame_should_wait_for_complete_server_frame() {
+            let raw = b"MESSAGE\nsubscription:sub-1\ncontent-length:3\n\na";
 
-        return data.join("");
+            assert_that(&StompFrame::decode(raw)).is_ok().is_none();
+        }
     }
-}
 
-impl StompClientFrame {
+    mod properties {
+        use super::*;
+        use crate::codec::StompDecoder;
+        use proptest::collection::{btree_map, vec};
+        use proptest::option;
+        use proptest::prelude::*;
+
+        /// Escaped header values, exercising every escape sequence.
+        fn header_value() -> impl Strategy<Value = String> {
+            "[a-z0-9:\\\\\r\n -]{0,12}"
+        }
 
-    /// # Examples
-    /// ```
-    /// use warp::ws::Message;
-    /// use spectral::prelude::*; 
-    /// use commenter_stomp::stomp::StompClientFrame;
-    /// 
-    /// let message = Message::text("DISCONNECT\n\n\0");
-    /// let result = StompClientFrame::new(&message);
-    /// assert_that(&result).is_ok_containing(StompClientFrame::DISCONNECT);
-    /// ```
-    #[inline]
-    pub fn new(msg: &Message) -> Result<StompClientFrame> {
-        let raw_str = str::from_utf8(msg.as_bytes())?;
+        /// Values of CONNECT frame headers, these are not escaped so can not contain EOLs.
+        fn connect_value() -> impl Strategy<Value = String> {
+            "[a-z0-9:\\\\ -]{0,12}"
+        }
 
-        let mut command: Option<String> = None;
-        let mut headers = HashMap::<String, String>::new();
-        let mut body: Option<Vec<u8>> = None;
+        fn body() -> impl Strategy<Value = String> {
+            prop_oneof![Just(String::new()), any::<String>()]
+        }
 
-        // COMMAND[\r]\n
-        // HEADER\n
-        //   .
-        //   .
-        // HEADER\n
-        // \n
-        // BODY..
-        // \0
+        fn connect_frame() -> impl Strategy<Value = ConnectClientFrame<'static>> {
+            (
+                vec("[0-9]\\.[0-9]", 1..4),
+                option::of(connect_value()),
+                option::of(connect_value()),
+                option::of(connect_value()),
+                any::<(u32, u32)>(),
+            )
+                .prop_map(|(versions, host, login, passcode, (outgoing, incoming))| {
+                    ConnectClientFrame {
+                        accept_version: versions.into_iter().map(Cow::Owned).collect(),
+                        host: host.map(Cow::Owned),
+                        login: login.map(Cow::Owned),
+                        passcode: passcode.map(Cow::Owned),
+                        heart_beat: HeartBeat { outgoing, incoming },
+                    }
+                })
+        }
 
-        let mut last_new_line_index = 0;
+        fn metadata() -> impl Strategy<Value = SendMetadata<'static>> {
+            (
+                option::of(header_value()),
+                option::of(header_value()),
+                option::of(header_value()),
+                btree_map("x-[a-z0-9-]{1,8}", header_value(), 0..3),
+            )
+                .prop_map(|(content_type, client_message_id, reply_to, custom)| {
+                    SendMetadata {
+                        content_type: content_type.map(Cow::Owned),
+                        client_message_id: client_message_id.map(Cow::Owned),
+                        reply_to: reply_to.map(Cow::Owned),
+                        custom: custom
+                            .into_iter()
+                            .map(|(key, value)| (key.into(), value.into()))
+                            .collect(),
+                    }
+                })
+        }
 
-        for (index, character) in raw_str.char_indices() {
-            if character != '\n' {
-                continue;
+        /// Metadata of a frame with `text`, which is serialized with the default `content-type` unless set.
+        fn text_metadata(text: &str, mut metadata: SendMetadata<'static>) -> SendMetadata<'static> {
+            if !text.is_empty() && metadata.content_type.is_none() {
+                metadata.content_type = Some(DEFAULT_CONTENT_TYPE.into());
             }
+            metadata
+        }
 
-            // we can use index as we know that \n is taking only single byte to encode in UTF-8
+        fn send_frame() -> impl Strategy<Value = SendClientFrame<'static>> {
+            prop_oneof![
+                (header_value(), body(), metadata()).prop_map(|(destination, text, metadata)| {
+                    SendClientFrame::CREATE {
+                        destination: destination.into(),
+                        metadata: text_metadata(&text, metadata),
+                        text: text.into(),
+                    }
+                }),
+                (header_value(), body(), metadata()).prop_map(|(id, text, metadata)| {
+                    SendClientFrame::UPDATE {
+                        id: id.into(),
+                        metadata: text_metadata(&text, metadata),
+                        text: text.into(),
+                    }
+                }),
+                (header_value(), metadata()).prop_map(|(id, metadata)| SendClientFrame::DELETE {
+                    id: id.into(),
+                    metadata
+                }),
+            ]
+        }
 
-            let fixed_index = if index > 1 && raw_str.as_bytes()[index - 1] as char == '\r' {
-                index - 1
-            } else {
-                index
-            };
+        fn client_frame() -> impl Strategy<Value = StompClientFrame<'static>> {
+            let ack = prop_oneof![
+                Just(AckMode::Auto),
+                Just(AckMode::Client),
+                Just(AckMode::ClientIndividual),
+            ];
+
+            prop_oneof![
+                connect_frame().prop_map(StompClientFrame::CONNECT),
+                connect_frame().prop_map(StompClientFrame::STOMP),
+                send_frame().prop_map(StompClientFrame::SEND),
+                (header_value(), header_value(), ack).prop_map(|(destination, id, ack)| {
+                    StompClientFrame::SUBSCRIBE {
+                        destination: destination.into(),
+                        id: id.into(),
+                        ack,
+                    }
+                }),
+                header_value().prop_map(|id| StompClientFrame::UNSUBSCRIBE(id.into())),
+                header_value().prop_map(|id| StompClientFrame::ACK(id.into())),
+                header_value().prop_map(|id| StompClientFrame::NACK(id.into())),
+                header_value().prop_map(|id| StompClientFrame::BEGIN(id.into())),
+                header_value().prop_map(|id| StompClientFrame::COMMIT(id.into())),
+                header_value().prop_map(|id| StompClientFrame::ABORT(id.into())),
+                Just(StompClientFrame::DISCONNECT),
+            ]
+        }
 
-            let new_line_shift = if fixed_index == index { 1 } else { 2 }; //TODO: this has to be better handled
-
-            if command.is_none() {
-                command = Some(raw_str[..fixed_index].to_owned());
-            } else if last_new_line_index != index - new_line_shift {
-                if (last_new_line_index + 1) != fixed_index {
-                    let header_splitted: Vec<String> = raw_str[(last_new_line_index + 1)..fixed_index]
-                        .split(':')
-                        .map(|el| el.to_owned())
-                        .collect();
-    
-                    if header_splitted.len() != 2 {
-                        bail!(
-                            "Unable to parse header {}",
-                            raw_str[(last_new_line_index + 1)..fixed_index].to_owned()
-                        );
+        /// Client frame together with the optional headers, transaction only applies to some frames
+        /// and headers of CONNECT frames are not escaped.
+        fn full_client_frame() -> impl Strategy<Value = ClientFrame<'static>> {
+            (
+                client_frame(),
+                option::of(header_value()),
+                option::of(header_value()),
+            )
+                .prop_map(|(frame, receipt, transaction)| {
+                    let transaction = transaction.filter(|_| {
+                        matches!(
+                            frame,
+                            StompClientFrame::SEND(_)
+                                | StompClientFrame::ACK(_)
+                                | StompClientFrame::NACK(_)
+                        )
+                    });
+
+                    let receipt = receipt.map(|receipt| match frame {
+                        StompClientFrame::CONNECT(_) | StompClientFrame::STOMP(_) => {
+                            receipt.replace(['\r', '\n'], "")
+                        }
+                        _ => receipt,
+                    });
+
+                    ClientFrame {
+                        frame,
+                        receipt: receipt.map(Cow::Owned),
+                        transaction: transaction.map(Cow::Owned),
                     }
-    
-                    headers.insert(header_splitted[0].clone(), header_splitted[1].clone());
+                })
+        }
+
+        fn server_frame() -> impl Strategy<Value = StompFrame> {
+            (
+                "[A-Z]{1,10}".prop_filter("frame is not escaped", |command| {
+                    !matches!(command.as_str(), "CONNECT" | "STOMP" | "CONNECTED")
+                }),
+                vec(("[a-z:\\\\\r\n]{1,8}", header_value()), 0..4),
+                body(),
+            )
+                .prop_map(|(command, headers, text)| StompFrame {
+                    command,
+                    headers,
+                    text,
+                })
+        }
+
+        /// Serializes the frame with LF or CRLF ending each command and header line, as chosen by `crlf`,
+        /// preceded by heart-beat EOLs.
+        fn encode_with_eols(frame: &ClientFrame<'_>, crlf: &[bool], heart_beats: &str) -> Vec<u8> {
+            let mut raw = Vec::new();
+            StompFrame::from(frame.clone()).encode(&mut raw);
+
+            // escaped header values never contain LF, so first empty line ends the headers
+            let headers_end = raw.windows(2).position(|eols| eols == b"\n\n").unwrap() + 1;
+            let mut result = heart_beats.as_bytes().to_vec();
+
+            for (position, byte) in raw.iter().enumerate() {
+                if *byte == b'\n' && position <= headers_end && crlf[position % crlf.len()] {
+                    result.push(b'\r');
                 }
-            } else {
-                body = Some(
-                    raw_str[(fixed_index + new_line_shift)..raw_str.len() - 1] //TODO: -1 it temp, we should handle NULL terminator \0
-                        .as_bytes()
-                        .into_iter()
-                        .map(|el| el.clone())
-                        .collect(),
-                );
+                result.push(*byte);
             }
 
-            last_new_line_index = index;
+            result
         }
 
-        return match command {
-            Some(cmd) => match cmd.as_str() {
-                "SEND" => StompClientFrame::crate_send_frame(headers, body),
-                "SUBSCRIBE" => StompClientFrame::create_subscribe_frame(headers),
-                "UNSUBSCRIBE" => StompClientFrame::create_unsubscribe_frame(headers),
-                "DISCONNECT" => Ok(StompClientFrame::DISCONNECT),
-                _ => bail!("Unrecogized command received {}", cmd),
-            },
-            None => bail!("Unable to parse STOMP command... command undetected..."),
-        };
-    }
+        proptest! {
+            #[test]
+            fn client_frame_should_survive_serialization_round_trip(
+                client_frame in full_client_frame(),
+                crlf in vec(any::<bool>(), 1..8),
+                heart_beats in "(\n|\r\n){0,3}",
+            ) {
+                let raw = encode_with_eols(&client_frame, &crlf, &heart_beats);
 
-    fn crate_send_frame(
-        headers: HashMap<String, String>,
-        payload: Option<Vec<u8>>,
-    ) -> Result<StompClientFrame> {
-        if let Some(action) = headers.get(ACTION) {
-            let send_frame = match action.as_str() {
-                "CREATE" => StompClientFrame::create_send_create_frame(
-                    headers,
-                    String::from_utf8(payload.unwrap_or_default())?,
-                ),
-                "UPDATE" => StompClientFrame::create_send_update_frame(
-                    headers,
-                    String::from_utf8(payload.unwrap_or_default())?,
-                ),
-                "DELETE" => StompClientFrame::create_send_delete_frame(headers),
-                _ => bail!("Urecognized action type"),
-            }?;
+                let (decoded, length) = StompClientFrame::decode(&raw).unwrap().unwrap();
 
-            return Ok(StompClientFrame::SEND(send_frame));
-        } else {
-            bail!("Action header not specified");
-        }
-    }
+                prop_assert_eq!(decoded, client_frame);
+                prop_assert_eq!(length, raw.len());
+            }
 
-    fn create_send_create_frame(
-        headers: HashMap<String, String>,
-        text: String,
-    ) -> Result<SendClientFrame> {
-        if let Some(destination) = headers.get(DESTINATION) {
-            return Ok(SendClientFrame::CREATE {
-                destination: destination.to_owned(),
-                text,
-            });
-        } else {
-            bail!("SEND frame with CREATE action requires DESTINATION to be specifed")
-        }
-    }
+            #[test]
+            fn server_frame_should_survive_serialization_round_trip(frame in server_frame()) {
+                let mut raw = Vec::new();
+                frame.encode(&mut raw);
 
-    fn create_send_update_frame(
-        headers: HashMap<String, String>,
-        text: String,
-    ) -> Result<SendClientFrame> {
-        if let Some(id) = headers.get(ID) {
-            return Ok(SendClientFrame::UPDATE {
-                id: id.to_owned(),
-                text,
-            });
-        } else {
-            bail!("SEND frame with UPDATE action requires ID to be specified")
-        }
-    }
+                let (decoded, length) = StompFrame::decode(&raw).unwrap().unwrap();
 
-    fn create_send_delete_frame(headers: HashMap<String, String>) -> Result<SendClientFrame> {
-        if let Some(id) = headers.get(ID) {
-            return Ok(SendClientFrame::DELETE { id: id.to_owned() });
-        } else {
-            bail!("SEND frame with DELETE action requires ID to be specifed")
-        }
-    }
+                // content headers computed by the serializer follow the frame's own headers
+                prop_assert_eq!(&decoded.command, &frame.command);
+                prop_assert_eq!(&decoded.headers[..frame.headers.len()], &frame.headers[..]);
+                prop_assert_eq!(&decoded.text, &frame.text);
+                prop_assert_eq!(length, raw.len());
+            }
 
-    fn create_subscribe_frame(headers: HashMap<String, String>) -> Result<StompClientFrame> {
-        if let Some(destination) = headers.get(DESTINATION) {
-            if let Some(id) = headers.get(ID) {
-                return Ok(StompClientFrame::SUBSCRIBE {
-                    destination: destination.to_owned(),
-                    id: id.to_owned(),
-                });
-            } else {
-                bail!("Id header not found for SUBSCRIBE command");
+            #[test]
+            fn decoder_should_decode_same_frames_regardless_of_chunking(
+                client_frames in vec(full_client_frame(), 1..5),
+                crlf in vec(any::<bool>(), 1..8),
+                chunk_size in 1..64usize,
+            ) {
+                let raw: Vec<u8> = client_frames
+                    .iter()
+                    .flat_map(|client_frame| encode_with_eols(client_frame, &crlf, "\n"))
+                    .collect();
+                let mut decoder = StompDecoder::new();
+                let mut decoded = Vec::new();
+
+                for chunk in raw.chunks(chunk_size) {
+                    decoded.extend(decoder.decode_chunk(chunk).unwrap());
+                }
+
+                prop_assert_eq!(decoded, client_frames);
+            }
+
+            #[test]
+            fn parser_should_not_panic_on_arbitrary_data(raw in vec(any::<u8>(), 0..256)) {
+                let _ = StompClientFrame::parse(&raw);
+                let _ = StompFrame::decode(&raw);
+            }
+
+            #[test]
+            fn parser_should_not_panic_on_damaged_frame(
+                client_frame in full_client_frame(),
+                damage in vec((any::<prop::sample::Index>(), any::<u8>()), 1..4),
+                truncation in any::<prop::sample::Index>(),
+                max_length in 0..64usize,
+            ) {
+                let mut raw = encode_with_eols(&client_frame, &[false], "");
+                for (index, byte) in damage {
+                    *index.get_mut(&mut raw) = byte;
+                }
+                raw.truncate(truncation.index(raw.len() + 1));
+
+                let limits = ParserLimits {
+                    max_frame_length: max_length * 4,
+                    max_headers: max_length / 8,
+                    max_header_line_length: max_length,
+                    max_body_length: max_length,
+                };
+
+                let _ = StompClientFrame::parse(&raw);
+                let _ = StompClientFrame::decode_with_limits(&raw, &limits);
             }
-        } else {
-            bail!("Destination header not found for SUBSCRIBE command");
         }
     }
 
-    fn create_unsubscribe_frame(headers: HashMap<String, String>) -> Result<StompClientFrame> {
-        if let Some(id) = headers.get(ID) {
-            return Ok(StompClientFrame::UNSUBSCRIBE(id.to_owned()));
-        } else {
-            bail!("Id header not found for SUBSCRIBE command");
+    mod limits {
+        use super::*;
+
+        const SEND: &[u8] = b"SEND\naction:CREATE\ndestination:topic-1\n\n0123456789\0";
+
+        #[test]
+        fn stomp_client_frame_should_accept_frame_within_limits() {
+            let limits = ParserLimits {
+                max_frame_length: SEND.len(),
+                max_headers: 2,
+                max_header_line_length: "destination:topic-1".len(),
+                max_body_length: 10,
+            };
+
+            assert_that(&StompClientFrame::parse_with_limits(SEND, &limits)).is_ok();
         }
-    }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use spectral::prelude::*;
-    use warp::filters::ws::Message;
+        #[test]
+        fn stomp_client_frame_should_reject_frame_exceeding_max_length() {
+            let limits = ParserLimits {
+                max_frame_length: SEND.len() - 1,
+                ..ParserLimits::default()
+            };
 
-    mod disconnect {
-        use super::*;
+            assert_that(&StompClientFrame::parse_with_limits(SEND, &limits))
+                .is_err_containing(StompParseError::FrameTooLarge(SEND.len() - 1));
+        }
 
         #[test]
-        fn stomp_client_frame_should_parse_disconnection_message_with_new_line_as_eol() {
-            test_stomp_client_frame_disconnection_message_parsing(false);
+        fn stomp_client_frame_should_reject_incomplete_frame_exceeding_max_length() {
+            let limits = ParserLimits {
+                max_frame_length: 20,
+                ..ParserLimits::default()
+            };
+
+            assert_that(&StompClientFrame::decode_with_limits(&SEND[..20], &limits))
+                .is_ok()
+                .is_none();
+            assert_that(&StompClientFrame::decode_with_limits(&SEND[..21], &limits))
+                .is_err_containing(StompParseError::FrameTooLarge(20));
         }
 
         #[test]
-        fn stomp_client_frame_should_parse_disconnection_message_with_carriage_return_included_in_eol(
-        ) {
-            test_stomp_client_frame_disconnection_message_parsing(true)
+        fn stomp_client_frame_should_not_count_leading_eols_into_frame_length() {
+            let limits = ParserLimits {
+                max_frame_length: SEND.len(),
+                ..ParserLimits::default()
+            };
+
+            assert_that(&StompClientFrame::parse_with_limits(
+                &[b"\n\n", SEND].concat(),
+                &limits,
+            ))
+            .is_ok();
         }
 
-        fn test_stomp_client_frame_disconnection_message_parsing(optional_carraige_return: bool) {
-            let input = encode_stomp_frame_command_only("DISCONNECT", optional_carraige_return);
-            test_stomp_client_frame_parsing(input, StompClientFrame::DISCONNECT);
+        #[test]
+        fn stomp_client_frame_should_reject_too_many_headers() {
+            let limits = ParserLimits {
+                max_headers: 1,
+                ..ParserLimits::default()
+            };
+
+            assert_that(&StompClientFrame::parse_with_limits(SEND, &limits))
+                .is_err_containing(StompParseError::TooManyHeaders(1));
         }
-    }
 
-    mod subscribe {
-        use super::*;
+        #[test]
+        fn stomp_client_frame_should_reject_too_long_header_line() {
+            let limits = ParserLimits {
+                max_header_line_length: "destination:topic-1".len() - 1,
+                ..ParserLimits::default()
+            };
+
+            assert_that(&StompClientFrame::parse_with_limits(SEND, &limits)).is_err_containing(
+                StompParseError::HeaderLineTooLong("destination:topic-1".len() - 1),
+            );
+        }
 
         #[test]
-        fn stomp_client_frame_should_parse_subscribe_message_with_new_line_as_eol() {
-            test_stomp_client_frame_subscribe_message_parsing("topic1", "sub-1", false)
+        fn stomp_client_frame_should_not_count_carriage_return_into_header_line_length() {
+            let limits = ParserLimits {
+                max_header_line_length: "id:sub-1".len(),
+                ..ParserLimits::default()
+            };
+
+            assert_that(&StompClientFrame::decode_with_limits(
+                b"UNSUBSCRIBE\r\nid:sub-1\r",
+                &limits,
+            ))
+            .is_ok()
+            .is_none();
         }
 
         #[test]
-        fn stomp_client_frame_should_parse_subscribe_message_with_carriage_return_included_in_eol()
-        {
-            test_stomp_client_frame_subscribe_message_parsing("topic1", "sub-1", true)
+        fn stomp_client_frame_should_reject_too_large_body() {
+            let limits = ParserLimits {
+                max_body_length: 9,
+                ..ParserLimits::default()
+            };
+
+            assert_that(&StompClientFrame::parse_with_limits(SEND, &limits))
+                .is_err_containing(StompParseError::BodyTooLarge(9));
         }
 
-        fn test_stomp_client_frame_subscribe_message_parsing(
-            destination: &str,
-            id: &str,
-            optional_carraige_return: bool,
-        ) {
-            let headers = HashMap::from([(DESTINATION, destination), (ID, id)]);
-            let input = encode_stomp_frame_command_with_headers(
-                "SUBSCRIBE",
-                headers,
-                optional_carraige_return,
-            );
+        #[test]
+        fn stomp_client_frame_should_reject_too_large_content_length_before_body_arrives() {
+            let limits = ParserLimits {
+                max_body_length: 1024,
+                ..ParserLimits::default()
+            };
 
-            test_stomp_client_frame_parsing(
-                input,
-                StompClientFrame::SUBSCRIBE {
-                    destination: destination.to_owned(),
-                    id: id.to_owned(),
-                },
-            );
+            assert_that(&StompClientFrame::decode_with_limits(
+                b"SEND\naction:CREATE\ndestination:topic-1\ncontent-length:1025\n\n",
+                &limits,
+            ))
+            .is_err_containing(StompParseError::BodyTooLarge(1024));
         }
     }
 
-    mod unsubscribe {
+    mod transaction {
         use super::*;
 
         #[test]
-        fn stomp_client_frame_should_parse_unsubscribe_message_with_new_line_as_eol() {
-            test_stomp_client_frame_unsubscribe_message_parsing("sub-1", false)
+        fn stomp_client_frame_should_parse_transaction_frames() {
+            test_stomp_client_frame_parsing(
+                "BEGIN\ntransaction:tx-1\n\n\0",
+                StompClientFrame::BEGIN("tx-1".into()),
+            );
+            test_stomp_client_frame_parsing(
+                "COMMIT\r\ntransaction:tx-1\r\n\r\n\0",
+                StompClientFrame::COMMIT("tx-1".into()),
+            );
+            test_stomp_client_frame_parsing(
+                "ABORT\ntransaction:tx\\\\1\n\n\0",
+                StompClientFrame::ABORT("tx\\1".into()),
+            );
         }
 
         #[test]
-        fn stomp_client_frame_should_parse_unsubscribe_message_with_carriage_return_included_in_eol(
-        ) {
-            test_stomp_client_frame_unsubscribe_message_parsing("sub-1", true)
+        fn stomp_client_frame_should_reject_transaction_frame_without_transaction() {
+            test_stomp_client_frame_parsing_failure(
+                "COMMIT\n\n\0",
+                StompParseError::MissingHeader {
+                    header: TRANSACTION,
+                    command: "COMMIT",
+                },
+            );
         }
 
-        fn test_stomp_client_frame_unsubscribe_message_parsing(
-            id: &str,
-            optional_carraige_return: bool,
-        ) {
-            let input = encode_stomp_frame_command_with_headers(
-                "UNSUBSCRIBE",
-                HashMap::from([(ID, id)]),
-                optional_carraige_return,
-            );
+        #[test]
+        fn stomp_client_frame_should_decode_transaction_of_send_frame() {
+            let raw = b"SEND\naction:DELETE\nid:comment-1\ntransaction:tx-1\n\n\0";
+
+            let result = StompClientFrame::decode(raw);
+
+            assert_that(&result).is_ok().is_some().is_equal_to((
+                ClientFrame {
+                    frame: StompClientFrame::SEND(SendClientFrame::DELETE {
+                        id: "comment-1".into(),
+                        metadata: SendMetadata::default(),
+                    }),
+                    receipt: None,
+                    transaction: Some("tx-1".into()),
+                },
+                raw.len(),
+            ));
+        }
 
-            test_stomp_client_frame_parsing(input, StompClientFrame::UNSUBSCRIBE(id.to_owned()))
+        #[test]
+        fn stomp_client_frame_should_ignore_transaction_of_frame_outside_transactions() {
+            let raw = b"SUBSCRIBE\ndestination:topic-1\nid:sub-1\ntransaction:tx-1\n\n\0";
+
+            let (client_frame, _) = StompClientFrame::decode(raw).unwrap().unwrap();
+
+            assert_that(&client_frame.transaction).is_none();
         }
     }
 
@@ -366,8 +3089,9 @@ mod tests {
                 test_stomp_client_frame_send_parsing(
                     input,
                     SendClientFrame::CREATE {
-                        destination: destination.to_owned(),
-                        text: body.to_owned(),
+                        destination: destination.into(),
+                        text: body.into(),
+                        metadata: SendMetadata::default(),
                     },
                 )
             }
@@ -405,8 +3129,9 @@ mod tests {
                 test_stomp_client_frame_send_parsing(
                     input,
                     SendClientFrame::UPDATE {
-                        id: id.to_owned(),
-                        text: body.to_owned(),
+                        id: id.into(),
+                        text: body.into(),
+                        metadata: SendMetadata::default(),
                     },
                 )
             }
@@ -442,7 +3167,10 @@ mod tests {
                 let input = encode_send_delete_stomp_frame(id, optional_carraige_return);
                 test_stomp_client_frame_send_parsing(
                     input,
-                    SendClientFrame::DELETE { id: id.to_owned() },
+                    SendClientFrame::DELETE {
+                        id: id.into(),
+                        metadata: SendMetadata::default(),
+                    },
                 )
             }
 
@@ -452,6 +3180,96 @@ mod tests {
             }
         }
 
+        mod metadata {
+            use super::*;
+
+            #[test]
+            fn stomp_client_frame_should_parse_send_metadata() {
+                test_stomp_client_frame_send_parsing(
+                    "SEND\naction:CREATE\ndestination:topic-1\ncontent-type:application/json\nclient-message-id:local\\c7\nreply-to:/queue/replies\nx-locale:en\nx-trace:abc\n\n{}\0".to_owned(),
+                    SendClientFrame::CREATE {
+                        destination: "topic-1".into(),
+                        text: "{}".into(),
+                        metadata: SendMetadata {
+                            content_type: Some("application/json".into()),
+                            client_message_id: Some("local:7".into()),
+                            reply_to: Some("/queue/replies".into()),
+                            custom: vec![
+                                ("x-locale".into(), "en".into()),
+                                ("x-trace".into(), "abc".into()),
+                            ],
+                        },
+                    },
+                );
+            }
+
+            #[test]
+            fn stomp_client_frame_should_take_first_occurrence_of_repeated_metadata() {
+                test_stomp_client_frame_send_parsing(
+                    "SEND\naction:DELETE\nid:comment-1\nclient-message-id:first\nx-locale:en\nclient-message-id:second\nx-locale:de\n\n\0".to_owned(),
+                    SendClientFrame::DELETE {
+                        id: "comment-1".into(),
+                        metadata: SendMetadata {
+                            client_message_id: Some("first".into()),
+                            custom: vec![("x-locale".into(), "en".into())],
+                            ..SendMetadata::default()
+                        },
+                    },
+                );
+            }
+
+            #[test]
+            fn stomp_client_frame_should_ignore_headers_other_than_metadata() {
+                let frame = StompClientFrame::parse(
+                    b"SEND\naction:DELETE\nid:comment-1\nreceipt:77\nlocale:en\n\n\0",
+                )
+                .unwrap();
+
+                let StompClientFrame::SEND(send_frame) = frame else {
+                    panic!("SEND frame expected");
+                };
+                assert_that(&send_frame.metadata().is_empty()).is_true();
+            }
+
+            #[test]
+            fn message_frame_should_carry_metadata() {
+                let metadata = SendMetadata {
+                    content_type: Some("application/json".into()),
+                    client_message_id: Some("local-7".into()),
+                    custom: vec![("x-locale".into(), "en".into())],
+                    ..SendMetadata::default()
+                };
+
+                let frame: String = StompFrame::message("topic-1", "sub-1", "message-1", "{}")
+                    .with_metadata(&metadata)
+                    .into();
+
+                assert_that(&frame).is_equal_to(
+                    "MESSAGE\ndestination:topic-1\nsubscription:sub-1\nmessage-id:message-1\ncontent-type:application/json\nclient-message-id:local-7\nx-locale:en\ncontent-length:2\n\n{}\0".to_owned(),
+                );
+            }
+
+            #[test]
+            fn send_frame_should_be_serialized_with_metadata() {
+                let frame: String = StompFrame::from(ClientFrame::from(StompClientFrame::SEND(
+                    SendClientFrame::UPDATE {
+                        id: "comment-1".into(),
+                        text: "text".into(),
+                        metadata: SendMetadata {
+                            client_message_id: Some("local-7".into()),
+                            reply_to: Some("/queue/replies".into()),
+                            ..SendMetadata::default()
+                        },
+                    },
+                )))
+                .into();
+
+                assert_that(&frame).is_equal_to(
+                    "SEND\naction:UPDATE\nid:comment-1\nclient-message-id:local-7\nreply-to:/queue/replies\ncontent-length:4\ncontent-type:text/plain;charset=utf-8\n\ntext\0".to_owned(),
+                );
+            }
+        }
+
         fn test_stomp_client_frame_send_parsing(input: String, output: SendClientFrame) {
             test_stomp_client_frame_parsing(input, StompClientFrame::SEND(output));
         }
@@ -471,12 +3289,18 @@ mod tests {
     where
         S: Into<String>,
     {
-        let message = Message::text(input);
-        let result = StompClientFrame::new(&message);
+        let input: String = input.into();
+        let result = StompClientFrame::parse(input.as_bytes());
 
         assert_that(&result).is_ok_containing(output);
     }
 
+    fn test_stomp_client_frame_parsing_failure(input: &str, error: StompParseError) {
+        let result = StompClientFrame::parse(input.as_bytes());
+
+        assert_that(&result).is_err_containing(error);
+    }
+
     fn encode_stomp_frame_command_only(command: &str, optional_carraige_return: bool) -> String {
         encode_stomp_frame(command, HashMap::new(), "", optional_carraige_return)
     }
@@ -506,6 +3330,6 @@ mod tests {
             .map(|(key, value)| format!("{key}:{value}"))
             .fold(String::from(""), |acc, next| format!("{acc}{eol}{next}"));
 
-        return format!("{command}{encoded_headers}{eol}{eol}{body}\0"); // <- eol between command and header is added due to fold first iteration
+        format!("{command}{encoded_headers}{eol}{eol}{body}\0") // <- eol between command and header is added due to fold first iteration
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,56 @@
+use tungstenite::Message;
+
+use crate::error::StompParseError;
+use crate::stomp::{StompClientFrame, StompFrame};
+
+impl<'a> TryFrom<&'a Message> for StompClientFrame<'a> {
+    type Error = StompParseError;
+
+    /// Parses websocket message that carries exactly one frame, text and binary messages are accepted.
+    fn try_from(msg: &'a Message) -> Result<StompClientFrame<'a>, StompParseError> {
+        match msg {
+            Message::Text(text) => StompClientFrame::parse(text.as_bytes()),
+            Message::Binary(data) => StompClientFrame::parse(data),
+            _ => Err(StompParseError::IncompleteFrame),
+        }
+    }
+}
+
+impl From<StompFrame> for Message {
+    /// Frames are sent as text messages.
+    fn from(frame: StompFrame) -> Message {
+        let data: String = frame.into();
+        Message::Text(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spectral::prelude::*;
+
+    #[test]
+    fn stomp_client_frame_should_parse_text_message() {
+        let message = Message::Text("DISCONNECT\n\n\0".to_owned());
+
+        assert_that(&StompClientFrame::try_from(&message))
+            .is_ok_containing(StompClientFrame::DISCONNECT);
+    }
+
+    #[test]
+    fn stomp_client_frame_should_reject_control_message() {
+        let message = Message::Ping(Vec::new());
+
+        assert_that(&StompClientFrame::try_from(&message))
+            .is_err_containing(StompParseError::IncompleteFrame);
+    }
+
+    #[test]
+    fn stomp_frame_should_convert_to_nul_terminated_text_message() {
+        let message = Message::from(StompFrame::receipt("receipt-1"));
+
+        assert_that(&message).is_equal_to(Message::Text(
+            "RECEIPT\nreceipt-id:receipt-1\n\n\0".to_owned(),
+        ));
+    }
+}
@@ -0,0 +1,42 @@
+use warp::ws::Message;
+
+use crate::error::StompParseError;
+use crate::stomp::{StompClientFrame, StompFrame};
+
+impl<'a> TryFrom<&'a Message> for StompClientFrame<'a> {
+    type Error = StompParseError;
+
+    /// Parses websocket message that carries exactly one frame, text and binary messages are accepted.
+    fn try_from(msg: &'a Message) -> Result<StompClientFrame<'a>, StompParseError> {
+        StompClientFrame::parse(msg.as_bytes())
+    }
+}
+
+impl From<StompFrame> for Message {
+    /// Frames are sent as text messages.
+    fn from(frame: StompFrame) -> Message {
+        let data: String = frame.into();
+        Message::text(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spectral::prelude::*;
+
+    #[test]
+    fn stomp_client_frame_should_parse_binary_message() {
+        let message = Message::binary(b"DISCONNECT\n\n\0".to_vec());
+
+        assert_that(&StompClientFrame::try_from(&message))
+            .is_ok_containing(StompClientFrame::DISCONNECT);
+    }
+
+    #[test]
+    fn stomp_frame_should_convert_to_nul_terminated_text_message() {
+        let message = Message::from(StompFrame::receipt("receipt-1"));
+
+        assert_that(&message.to_str()).is_ok_containing("RECEIPT\nreceipt-id:receipt-1\n\n\0");
+    }
+}